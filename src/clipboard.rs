@@ -0,0 +1,64 @@
+//! Copying to the clipboard of the terminal with the OSC 52 escape sequence, which reaches the
+//! clipboard of the machine the terminal runs on, even over SSH.
+use std::{
+    io::{
+        Write,
+        Result as IoResult,
+    },
+    env::var_os,
+};
+
+
+const BASE64:&[u8;64]=b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+
+/// Asks the terminal to put `text` on the clipboard. Inside tmux the sequence is wrapped so tmux
+/// passes it on to the terminal.
+pub fn copy(out:&mut impl Write,text:&str)->IoResult<()> {
+    let sequence=format!("\x1b]52;c;{}\x07",base64(text.as_bytes()));
+    if var_os("TMUX").is_some() {
+        // escapes inside the passthrough are doubled
+        write!(out,"\x1bPtmux;{}\x1b\\",sequence.replace('\x1b',"\x1b\x1b"))?;
+    } else {
+        out.write_all(sequence.as_bytes())?;
+    }
+    out.flush()
+}
+
+
+fn base64(bytes:&[u8])->String {
+    let mut out=String::new();
+    for chunk in bytes.chunks(3) {
+        let group=chunk.iter()
+            .enumerate()
+            .fold(0u32,|group,(i,byte)|group|(*byte as u32)<<(16-8*i));
+        for i in 0..4 {
+            if i<=chunk.len() {
+                out.push(BASE64[(group>>(18-6*i)) as usize&63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc_4648_vectors() {
+        // one, two and zero bytes left over give two, one and no padding chars
+        let vectors=[("",""),("f","Zg=="),("fo","Zm8="),("foo","Zm9v"),("foob","Zm9vYg=="),("fooba","Zm9vYmE="),("foobar","Zm9vYmFy")];
+        for (text,encoded) in vectors {
+            assert_eq!(base64(text.as_bytes()),encoded,"encoding `{}`",text);
+        }
+    }
+    #[test]
+    fn high_bytes() {
+        assert_eq!(base64(&[0xff,0xfe,0xfd]),"//79");
+        assert_eq!(base64("é".as_bytes()),"w6k=");
+    }
+}
@@ -8,7 +8,17 @@ pub struct Colors {
     pub ident:Color,
     pub number:Color,
     pub string:Color,
+    pub special_form:Color,
+    pub definition:Color,
+    pub constant:Color,
+    pub keyword:Color,
     pub statusline:Color,
+    pub selection:Color,
+    /// The background of the delimiters of the list the cursor is in.
+    pub matching:Color,
+    pub error:Color,
+    pub warning:Color,
+    pub info:Color,
 }
 impl Default for Colors {
     fn default()->Self {
@@ -21,6 +31,7 @@ impl Default for Colors {
         let green=Color::Rgb(0xB8,0xEE,0x92);
         let white=Color::Rgb(0xCE,0xD5,0xE5);
         let grey=Color::Rgb(0x49,0x46,0x46);
+        let slate=Color::Rgb(0x3A,0x3F,0x5A);
         Colors {
             rainbow:vec![
                 red,
@@ -34,7 +45,16 @@ impl Default for Colors {
             ident:white,
             number:red,
             string:green,
+            special_form:purple,
+            definition:yellow,
+            constant:orange,
+            keyword:aqua,
             statusline:grey,
+            selection:slate,
+            matching:grey,
+            error:red,
+            warning:yellow,
+            info:cyan,
         }
     }
 }
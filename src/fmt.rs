@@ -0,0 +1,166 @@
+//! The `fmt` subcommand: lays files out with the same printer `:w` uses, without opening the
+//! editor. Meant for scripts and pre-commit hooks.
+use clap::{
+    Command,
+    Arg,
+    ArgMatches,
+    builder::PossibleValuesParser,
+    value_parser,
+};
+use std::fs::read_to_string;
+use crate::{
+    object::*,
+    save::write_atomic,
+};
+
+
+/// Every file was formatted already, or was formatted successfully.
+pub const EXIT_OK:i32=0;
+/// `--check` found a file that is not formatted.
+pub const EXIT_UNFORMATTED:i32=1;
+/// A file could not be read, parsed or written.
+pub const EXIT_ERROR:i32=2;
+
+
+pub fn command()->Command<'static> {
+    Command::new("fmt")
+        .about("Formats files in place with the same layout the editor saves")
+        .arg(Arg::new("check")
+            .long("check")
+            .help("Only check the files. Exits with 1 if one of them is not formatted"))
+        .arg(Arg::new("width")
+            .long("width")
+            .takes_value(true)
+            .value_parser(value_parser!(u64).range(1..))
+            .help("The line width, like `:set width`"))
+        .arg(Arg::new("indent")
+            .long("indent")
+            .takes_value(true)
+            .value_parser(PossibleValuesParser::new(IndentRules::NAMES.iter().copied()))
+            .help("The indent style, like `:set indent`. Defaults to the style of the dialect"))
+        .arg(Arg::new("dialect")
+            .long("dialect")
+            .takes_value(true)
+            .value_parser(PossibleValuesParser::new(Dialect::names()))
+            .help("The dialect to read the files as, like `:set dialect`. Picked by extension by default"))
+        .arg(Arg::new("files")
+            .required(true)
+            .multiple_values(true)
+            .value_parser(value_parser!(String)))
+}
+/// Runs `fmt` with its parsed arguments and returns the exit code.
+pub fn run(matches:&ArgMatches)->i32 {
+    let check=matches.contains_id("check");
+    let mut code=EXIT_OK;
+    for filename in matches.get_many::<String>("files").into_iter().flatten() {
+        let dialect=match matches.get_one::<String>("dialect") {
+            Some(name)=>Dialect::named(name).unwrap(),
+            None=>Dialect::for_file(filename),
+        };
+        let indent=matches.get_one::<String>("indent")
+            .map_or(dialect.indent,String::as_str);
+        let format=Format {
+            width:matches.get_one::<u64>("width").map_or(DEFAULT_WIDTH,|width|*width as usize),
+            indent:IndentRules::named(indent).unwrap_or_default(),
+        };
+        match format_file(filename,&format,dialect,check) {
+            Ok(true)=>{},
+            Ok(false)=>{
+                println!("`{}` is not formatted",filename);
+                code=code.max(EXIT_UNFORMATTED);
+            },
+            Err(e)=>{
+                eprintln!("{}",e);
+                code=EXIT_ERROR;
+            },
+        }
+    }
+    code
+}
+/// Formats one file. Returns whether it was formatted already. With `check` the file is never
+/// written.
+fn format_file(filename:&str,format:&Format,dialect:&Dialect,check:bool)->Result<bool,String> {
+    let text=read_to_string(filename)
+        .map_err(|e|format!("Could not read `{}`. Reason: {}",filename,e))?;
+    let out=format_text(&text,format,dialect)
+        .map_err(|e|format!("Could not parse `{}`. Reason: {}",filename,e))?;
+    if out==text {
+        return Ok(true);
+    }
+    if check {
+        return Ok(false);
+    }
+    write_atomic(filename,&out,false)
+        .map_err(|e|format!("Could not save `{}`. Reason: {}",filename,e))?;
+    Ok(true)
+}
+/// Lays out the text with the preserving writer `:w` uses, so everything between top-level forms
+/// is kept as it is. Every form is laid out again, along with the comments in it.
+fn format_text(text:&str,format:&Format,dialect:&Dialect)->Result<String,String> {
+    let mut objects=Object::parse_file(text,dialect)?;
+    let original=Original::new(text.to_string(),&objects);
+    let mut commented=Vec::new();
+    for (i,object) in objects.iter_mut().enumerate() {
+        let comments=match object.span() {
+            Some(span)=>dialect.mask(&text[span.start..span.end]).1,
+            None=>false,
+        };
+        if comments {
+            commented.push(i);
+        } else {
+            object.forget_item_spans();
+        }
+    }
+    // forms with comments are copied by the preserving writer, then replaced with their layout
+    let map=SourceMap::preserving(&objects,&original,format);
+    let mut out=map.text;
+    for i in commented.into_iter().rev() {
+        if let Some(span)=map.spans.iter().find(|span|span.path==[i]) {
+            let column=span.start.column;
+            let laid_out=Layout::commented(&objects[i],column,format,&comments_in(&objects[i],text)).text();
+            out.replace_range(span.bytes.clone(),laid_out[column..].trim_end_matches('\n'));
+        }
+    }
+    Ok(out)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_comments() {
+        let format=Format {
+            width:16,
+            indent:IndentRules::named("scheme").unwrap(),
+        };
+        let dialect=Dialect::named("scheme").unwrap();
+        let text="; header\n(define   (f x)\n   (g x))  ; after\n\n(define (h x)\n  ; inside\n  x)\n";
+        let formatted="; header\n(define (f x)\n  (g x))  ; after\n\n(define (h x)\n  ; inside\n  x)\n";
+        assert_eq!(format_text(text,&format,dialect).unwrap(),formatted);
+        assert_eq!(format_text(formatted,&format,dialect).unwrap(),formatted);
+    }
+    #[test]
+    fn lays_out_forms_with_comments() {
+        let format=Format {
+            width:40,
+            indent:IndentRules::named("scheme").unwrap(),
+        };
+        let dialect=Dialect::named("scheme").unwrap();
+        let text="(define (f x)\n      (g x) ; note\n   ; own line\n      (h x))\n(define (k x) ; why\n (let ((y x)) y   ; first\n  ))\n";
+        let formatted="(define (f x)\n  (g x) ; note\n  ; own line\n  (h x))\n(define (k x) ; why\n  (let ((y x))\n    y ; first\n  ))\n";
+        assert_eq!(format_text(text,&format,dialect).unwrap(),formatted);
+        assert_eq!(format_text(formatted,&format,dialect).unwrap(),formatted);
+    }
+    #[test]
+    fn keeps_escaped_newlines() {
+        let format=Format {
+            width:80,
+            indent:IndentRules::named("scheme").unwrap(),
+        };
+        let dialect=Dialect::named("scheme").unwrap();
+        let text="(display \"a\\nb\")\n";
+        assert_eq!(format_text(text,&format,dialect).unwrap(),text);
+    }
+}
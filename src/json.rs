@@ -0,0 +1,277 @@
+//! Just enough JSON for the language server protocol.
+use std::{
+    collections::BTreeMap,
+    fmt::{
+        Display,
+        Formatter,
+        Result as FmtResult,
+    },
+    iter::Peekable,
+    str::Chars,
+};
+
+
+#[derive(Debug,Clone,PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Self>),
+    Object(BTreeMap<String,Self>),
+}
+impl Json {
+    /// Builds an object from pairs.
+    pub fn object<const N:usize>(pairs:[(&str,Json);N])->Self {
+        Json::Object(pairs.into_iter().map(|(key,value)|(key.to_string(),value)).collect())
+    }
+    pub fn parse(text:&str)->Result<Self,String> {
+        let mut chars=text.chars().peekable();
+        let value=parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            None=>Ok(value),
+            Some(c)=>Err(format!("Unexpected `{}` after the value",c)),
+        }
+    }
+    /// The value at `key` if this is an object, otherwise `Null`.
+    pub fn get(&self,key:&str)->&Self {
+        match self {
+            Self::Object(pairs)=>pairs.get(key).unwrap_or(&Json::Null),
+            _=>&Json::Null,
+        }
+    }
+    pub fn as_str(&self)->Option<&str> {
+        match self {
+            Self::String(s)=>Some(s),
+            _=>None,
+        }
+    }
+    pub fn as_usize(&self)->Option<usize> {
+        match self {
+            Self::Number(n) if *n>=0.0=>Some(*n as usize),
+            _=>None,
+        }
+    }
+    pub fn as_array(&self)->&[Self] {
+        match self {
+            Self::Array(items)=>items,
+            _=>&[],
+        }
+    }
+    pub fn is_null(&self)->bool {
+        *self==Json::Null
+    }
+}
+impl From<&str> for Json {
+    fn from(s:&str)->Self {
+        Json::String(s.to_string())
+    }
+}
+impl From<String> for Json {
+    fn from(s:String)->Self {
+        Json::String(s)
+    }
+}
+impl From<usize> for Json {
+    fn from(n:usize)->Self {
+        Json::Number(n as f64)
+    }
+}
+impl From<bool> for Json {
+    fn from(b:bool)->Self {
+        Json::Bool(b)
+    }
+}
+impl Display for Json {
+    fn fmt(&self,f:&mut Formatter)->FmtResult {
+        match self {
+            Self::Null=>write!(f,"null"),
+            Self::Bool(b)=>write!(f,"{}",b),
+            Self::Number(n) if n.fract()==0.0&&n.abs()<1e15=>write!(f,"{}",*n as i64),
+            Self::Number(n)=>write!(f,"{}",n),
+            Self::String(s)=>write_string(f,s),
+            Self::Array(items)=>{
+                write!(f,"[")?;
+                for (i,item) in items.iter().enumerate() {
+                    if i>0 {
+                        write!(f,",")?;
+                    }
+                    write!(f,"{}",item)?;
+                }
+                write!(f,"]")
+            },
+            Self::Object(pairs)=>{
+                write!(f,"{{")?;
+                for (i,(key,value)) in pairs.iter().enumerate() {
+                    if i>0 {
+                        write!(f,",")?;
+                    }
+                    write_string(f,key)?;
+                    write!(f,":{}",value)?;
+                }
+                write!(f,"}}")
+            },
+        }
+    }
+}
+
+
+fn write_string(f:&mut Formatter,s:&str)->FmtResult {
+    write!(f,"\"")?;
+    for c in s.chars() {
+        match c {
+            '"'=>write!(f,"\\\"")?,
+            '\\'=>write!(f,"\\\\")?,
+            '\n'=>write!(f,"\\n")?,
+            '\r'=>write!(f,"\\r")?,
+            '\t'=>write!(f,"\\t")?,
+            c if (c as u32)<0x20=>write!(f,"\\u{:04x}",c as u32)?,
+            c=>write!(f,"{}",c)?,
+        }
+    }
+    write!(f,"\"")
+}
+fn skip_whitespace(chars:&mut Peekable<Chars>) {
+    while matches!(chars.peek(),Some(c) if c.is_whitespace()) {
+        chars.next();
+    }
+}
+fn expect(chars:&mut Peekable<Chars>,word:&str)->Result<(),String> {
+    for expected in word.chars() {
+        if chars.next()!=Some(expected) {
+            return Err(format!("Expected `{}`",word));
+        }
+    }
+    Ok(())
+}
+fn parse_value(chars:&mut Peekable<Chars>)->Result<Json,String> {
+    skip_whitespace(chars);
+    match chars.peek().copied() {
+        None=>Err("Unexpected end of input".to_string()),
+        Some('n')=>expect(chars,"null").map(|_|Json::Null),
+        Some('t')=>expect(chars,"true").map(|_|Json::Bool(true)),
+        Some('f')=>expect(chars,"false").map(|_|Json::Bool(false)),
+        Some('"')=>parse_string(chars).map(Json::String),
+        Some('[')=>{
+            chars.next();
+            let mut items=Vec::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.peek()==Some(&']') {
+                    chars.next();
+                    return Ok(Json::Array(items));
+                }
+                if !items.is_empty() {
+                    expect(chars,",")?;
+                }
+                items.push(parse_value(chars)?);
+            }
+        },
+        Some('{')=>{
+            chars.next();
+            let mut pairs=BTreeMap::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.peek()==Some(&'}') {
+                    chars.next();
+                    return Ok(Json::Object(pairs));
+                }
+                if !pairs.is_empty() {
+                    expect(chars,",")?;
+                    skip_whitespace(chars);
+                }
+                let key=parse_string(chars)?;
+                skip_whitespace(chars);
+                expect(chars,":")?;
+                pairs.insert(key,parse_value(chars)?);
+            }
+        },
+        Some(c) if c=='-'||c.is_ascii_digit()=>{
+            let mut number=String::new();
+            while let Some(c)=chars.peek().copied().filter(|c|c.is_ascii_digit()||"+-.eE".contains(*c)) {
+                number.push(c);
+                chars.next();
+            }
+            number.parse().map(Json::Number).map_err(|_|format!("Invalid number `{}`",number))
+        },
+        Some(c)=>Err(format!("Unexpected `{}`",c)),
+    }
+}
+fn parse_string(chars:&mut Peekable<Chars>)->Result<String,String> {
+    expect(chars,"\"")?;
+    let mut out=String::new();
+    loop {
+        match chars.next() {
+            None=>return Err("Unclosed string".to_string()),
+            Some('"')=>return Ok(out),
+            Some('\\')=>match chars.next() {
+                Some('n')=>out.push('\n'),
+                Some('r')=>out.push('\r'),
+                Some('t')=>out.push('\t'),
+                Some('b')=>out.push('\u{8}'),
+                Some('f')=>out.push('\u{c}'),
+                Some('u')=>{
+                    let mut code=parse_hex(chars)?;
+                    // characters outside the basic plane come as a surrogate pair
+                    if (0xD800..0xDC00).contains(&code) {
+                        expect(chars,"\\u")?;
+                        let low=parse_hex(chars)?;
+                        code=0x10000+((code-0xD800)<<10)+(low.wrapping_sub(0xDC00)&0x3FF);
+                    }
+                    out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                },
+                Some(c)=>out.push(c),
+                None=>return Err("Unclosed string".to_string()),
+            },
+            Some(c)=>out.push(c),
+        }
+    }
+}
+fn parse_hex(chars:&mut Peekable<Chars>)->Result<u32,String> {
+    let hex:String=chars.take(4).collect();
+    u32::from_str_radix(&hex,16).map_err(|_|format!("Invalid escape `\\u{}`",hex))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings() {
+        let parsed=Json::parse(r#""a\"b\\c\/d\n\té😀""#).unwrap();
+        assert_eq!(parsed,Json::String("a\"b\\c/d\n\té😀".to_string()));
+        // control characters without a short escape are written as `\u`
+        let written=Json::from("a\"b\\c\n\u{1}é😀").to_string();
+        assert_eq!(written,r#""a\"b\\c\n\u0001é😀""#);
+        assert_eq!(Json::parse(&written).unwrap(),Json::from("a\"b\\c\n\u{1}é😀"));
+        // characters outside the basic plane are escaped as surrogate pairs
+        assert_eq!(Json::parse(r#""\u00e9\ud83d\ude00""#).unwrap(),Json::from("é😀"));
+        assert!(Json::parse(r#""open"#).is_err());
+        assert!(Json::parse(r#""\uZZZZ""#).is_err());
+    }
+    #[test]
+    fn numbers() {
+        assert_eq!(Json::parse("42").unwrap(),Json::Number(42.0));
+        assert_eq!(Json::parse("-2.5e2").unwrap(),Json::Number(-250.0));
+        assert_eq!(Json::parse("42").unwrap().as_usize(),Some(42));
+        assert_eq!(Json::parse("-1").unwrap().as_usize(),None);
+        assert_eq!(Json::Number(3.0).to_string(),"3");
+        assert_eq!(Json::Number(0.5).to_string(),"0.5");
+        assert!(Json::parse("1-").is_err());
+    }
+    #[test]
+    fn nesting() {
+        let text=r#" {"a": [1, {"b": null}, true], "c": {}, "d": []} "#;
+        let parsed=Json::parse(text).unwrap();
+        assert_eq!(parsed.get("a").as_array().len(),3);
+        assert!(parsed.get("a").as_array()[1].get("b").is_null());
+        assert_eq!(parsed.get("a").as_array()[2],Json::Bool(true));
+        assert_eq!(parsed.get("missing"),&Json::Null);
+        assert_eq!(parsed.to_string(),r#"{"a":[1,{"b":null},true],"c":{},"d":[]}"#);
+        assert_eq!(Json::parse(&parsed.to_string()).unwrap(),parsed);
+        assert!(Json::parse("[1,]").is_err());
+        assert!(Json::parse("[1] 2").is_err());
+    }
+}
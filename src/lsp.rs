@@ -0,0 +1,640 @@
+//! A client for language servers, talking JSON-RPC over the stdin and stdout of the server
+//! process. The document the server sees is the buffer as it is laid out on screen, so positions
+//! in its answers can be mapped back to nodes with the layout.
+use std::{
+    collections::HashMap,
+    process::{
+        Command,
+        Child,
+        ChildStdin,
+        Stdio,
+        id as process_id,
+    },
+    io::{
+        BufRead,
+        BufReader,
+        Write,
+        Error as IoError,
+        ErrorKind,
+        Result as IoResult,
+    },
+    sync::mpsc::{
+        channel,
+        Receiver,
+    },
+    fs::canonicalize,
+    path::Path,
+    time::{
+        Duration,
+        Instant,
+    },
+    thread,
+};
+use crate::{
+    json::Json,
+    object::{
+        Position,
+        Severity,
+    },
+};
+
+
+/// How long to wait for the server to answer `shutdown` before it is killed.
+const SHUTDOWN_TIMEOUT:Duration=Duration::from_secs(1);
+
+
+/// A place in a file the server pointed at.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Location {
+    pub uri:String,
+    pub start:Position,
+}
+/// A replacement of the text between two positions.
+#[derive(Debug,Clone,PartialEq)]
+pub struct TextEdit {
+    pub start:Position,
+    pub end:Position,
+    pub text:String,
+}
+/// Something from the server the editor has to act on.
+#[derive(Debug,Clone,PartialEq)]
+pub enum LspEvent {
+    /// Every problem the server currently sees in the document: where it starts and ends, how bad
+    /// it is and what it is.
+    Diagnostics(Vec<(Position,Position,Severity,String)>),
+    Hover(String),
+    Definition(Vec<Location>),
+    /// The edits of a rename for each file, by URI.
+    Rename(Vec<(String,Vec<TextEdit>)>),
+    /// Errors and messages meant for the user.
+    Message(String),
+}
+
+
+/// What a request that is waiting for its answer asked for.
+enum Request {
+    Initialize,
+    Hover,
+    Definition,
+    Rename,
+}
+
+
+pub struct Lsp {
+    /// The command the server was started with.
+    pub name:String,
+    child:Child,
+    stdin:ChildStdin,
+    receiver:Receiver<Result<Json,String>>,
+    next_id:usize,
+    pending:HashMap<usize,Request>,
+    /// The URI of the document being edited.
+    pub uri:String,
+    language:&'static str,
+    version:usize,
+    /// The latest text of the document, which is sent when the server is ready.
+    text:String,
+    /// The server is initialized and has the document open.
+    opened:bool,
+}
+impl Lsp {
+    /// Starts the server `command` for `filename`, which is in the language `language`.
+    pub fn start(command:&str,filename:&str,language:&'static str)->IoResult<Self> {
+        let mut words=command.split_whitespace();
+        let program=words.next()
+            .ok_or_else(||IoError::new(ErrorKind::InvalidInput,"no command given"))?;
+        let mut child=Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin=child.stdin.take().unwrap();
+        let stdout=child.stdout.take().unwrap();
+        let (sender,receiver)=channel();
+        thread::spawn(move||{
+            let mut stdout=BufReader::new(stdout);
+            loop {
+                let message=read_message(&mut stdout);
+                let closed=message.is_err();
+                if sender.send(message).is_err()||closed {
+                    break;
+                }
+            }
+        });
+        let cwd=canonicalize(".")?;
+        let path=canonicalize(filename).unwrap_or_else(|_|cwd.join(filename));
+        let mut lsp=Lsp {
+            name:command.to_string(),
+            child,
+            stdin,
+            receiver,
+            next_id:0,
+            pending:HashMap::new(),
+            uri:file_uri(&path),
+            language,
+            version:0,
+            text:String::new(),
+            opened:false,
+        };
+        let params=Json::object([
+            ("processId",(process_id() as usize).into()),
+            ("rootUri",file_uri(&cwd).into()),
+            ("capabilities",Json::object([
+                ("textDocument",Json::object([
+                    ("hover",Json::object([("contentFormat",Json::Array(vec!["plaintext".into()]))])),
+                    ("definition",Json::object([])),
+                    ("rename",Json::object([])),
+                    ("publishDiagnostics",Json::object([])),
+                ])),
+            ])),
+        ]);
+        lsp.request(Request::Initialize,"initialize",params)?;
+        Ok(lsp)
+    }
+    /// Tells the server about the new text of the document if it changed.
+    pub fn sync(&mut self,text:String)->IoResult<()> {
+        if text==self.text {
+            return Ok(());
+        }
+        self.text=text;
+        if self.opened {
+            self.version+=1;
+            let params=Json::object([
+                ("textDocument",Json::object([
+                    ("uri",self.uri.as_str().into()),
+                    ("version",self.version.into()),
+                ])),
+                ("contentChanges",Json::Array(vec![Json::object([("text",self.text.as_str().into())])])),
+            ]);
+            self.notify("textDocument/didChange",params)?;
+        }
+        Ok(())
+    }
+    pub fn hover(&mut self,position:Position)->IoResult<()> {
+        let params=self.position_params(position)?;
+        self.request(Request::Hover,"textDocument/hover",params)
+    }
+    pub fn definition(&mut self,position:Position)->IoResult<()> {
+        let params=self.position_params(position)?;
+        self.request(Request::Definition,"textDocument/definition",params)
+    }
+    pub fn rename(&mut self,position:Position,new_name:&str)->IoResult<()> {
+        let mut params=self.position_params(position)?;
+        if let Json::Object(pairs)=&mut params {
+            pairs.insert("newName".to_string(),new_name.into());
+        }
+        self.request(Request::Rename,"textDocument/rename",params)
+    }
+    /// Checks if the server process is still alive.
+    pub fn is_running(&mut self)->bool {
+        matches!(self.child.try_wait(),Ok(None))
+    }
+    /// Handles everything the server sent since the last call, returning what the editor has to
+    /// act on.
+    pub fn poll(&mut self)->IoResult<Vec<LspEvent>> {
+        let mut events=Vec::new();
+        while let Ok(message)=self.receiver.try_recv() {
+            let message=match message {
+                Ok(message)=>message,
+                Err(e)=>{
+                    events.push(LspEvent::Message(e));
+                    continue;
+                },
+            };
+            match (message.get("method").as_str(),message.get("id")) {
+                // a request from the server. Nothing it asks for is supported, so answer with
+                // empty results so it doesn't wait forever
+                (Some(method),id) if !id.is_null()=>{
+                    let result=match method {
+                        "workspace/configuration"=>Json::Array(vec![Json::Null;message.get("params").get("items").as_array().len()]),
+                        _=>Json::Null,
+                    };
+                    self.send(Json::object([
+                        ("jsonrpc","2.0".into()),
+                        ("id",id.clone()),
+                        ("result",result),
+                    ]))?;
+                },
+                (Some("textDocument/publishDiagnostics"),_)=>{
+                    let params=message.get("params");
+                    if matches!(params.get("uri").as_str(),Some(uri) if self.is_document(uri)) {
+                        let diagnostics=params.get("diagnostics").as_array().iter()
+                            .filter_map(|diagnostic|{
+                                let (start,end)=range(diagnostic.get("range"))?;
+                                let (start,end)=(self.editor_position(start),self.editor_position(end));
+                                let severity=match diagnostic.get("severity").as_usize() {
+                                    Some(1)|None=>Severity::Error,
+                                    Some(2)=>Severity::Warning,
+                                    _=>Severity::Info,
+                                };
+                                let text=diagnostic.get("message").as_str().unwrap_or("").to_string();
+                                Some((start,end,severity,text))
+                            })
+                            .collect();
+                        events.push(LspEvent::Diagnostics(diagnostics));
+                    }
+                },
+                (Some("window/showMessage"),_)=>if let Some(text)=message.get("params").get("message").as_str() {
+                    events.push(LspEvent::Message(text.to_string()));
+                },
+                (Some(_),_)=>{},
+                (None,id)=>{
+                    let request=match id.as_usize().and_then(|id|self.pending.remove(&id)) {
+                        Some(request)=>request,
+                        None=>continue,
+                    };
+                    if let Some(error)=message.get("error").get("message").as_str() {
+                        events.push(LspEvent::Message(format!("{}: {}",self.name,error)));
+                        continue;
+                    }
+                    let result=message.get("result");
+                    match request {
+                        Request::Initialize=>self.open()?,
+                        Request::Hover=>events.push(LspEvent::Hover(hover_text(result.get("contents")))),
+                        Request::Definition=>{
+                            let locations:Vec<Location>=match result {
+                                Json::Array(items)=>items.iter().filter_map(location).collect(),
+                                result=>location(result).into_iter().collect(),
+                            };
+                            let locations=locations.into_iter()
+                                .map(|location|if self.is_document(&location.uri) {
+                                    Location{uri:self.uri.clone(),start:self.editor_position(location.start)}
+                                } else {
+                                    location
+                                })
+                                .collect();
+                            events.push(LspEvent::Definition(locations));
+                        },
+                        Request::Rename=>{
+                            let files=workspace_edit(result).into_iter()
+                                .map(|(uri,edits)|if self.is_document(&uri) {
+                                    let edits=edits.into_iter()
+                                        .map(|edit|TextEdit{start:self.editor_position(edit.start),end:self.editor_position(edit.end),..edit})
+                                        .collect();
+                                    (self.uri.clone(),edits)
+                                } else {
+                                    (uri,edits)
+                                })
+                                .collect();
+                            events.push(LspEvent::Rename(files));
+                        },
+                    }
+                },
+            }
+        }
+        Ok(events)
+    }
+    /// Finishes initializing and opens the document.
+    fn open(&mut self)->IoResult<()> {
+        self.notify("initialized",Json::object([]))?;
+        let params=Json::object([
+            ("textDocument",Json::object([
+                ("uri",self.uri.as_str().into()),
+                ("languageId",self.language.into()),
+                ("version",self.version.into()),
+                ("text",self.text.as_str().into()),
+            ])),
+        ]);
+        self.notify("textDocument/didOpen",params)?;
+        self.opened=true;
+        Ok(())
+    }
+    fn position_params(&self,position:Position)->IoResult<Json> {
+        if !self.opened {
+            return Err(IoError::new(ErrorKind::WouldBlock,"the server is still starting"));
+        }
+        let position=self.server_position(position);
+        Ok(Json::object([
+            ("textDocument",Json::object([("uri",self.uri.as_str().into())])),
+            ("position",Json::object([
+                ("line",position.line.into()),
+                ("character",position.column.into()),
+            ])),
+        ]))
+    }
+    /// Checks if `uri` is the document being edited. Servers may encode the same path in another
+    /// way, so the paths are compared.
+    fn is_document(&self,uri:&str)->bool {
+        uri==self.uri||uri_path(uri)==uri_path(&self.uri)
+    }
+    /// The line of the document a position is on.
+    fn line(&self,line:usize)->&str {
+        self.text.split('\n').nth(line).unwrap_or("")
+    }
+    /// Converts a column counted in chars, like the layout counts, to UTF-16 code units, which is
+    /// how servers count.
+    fn server_position(&self,position:Position)->Position {
+        let column=self.line(position.line).chars()
+            .take(position.column)
+            .map(char::len_utf16)
+            .sum();
+        Position{column,..position}
+    }
+    /// Converts a column the server sent in UTF-16 code units to chars.
+    fn editor_position(&self,position:Position)->Position {
+        let mut units=0;
+        let column=self.line(position.line).chars()
+            .take_while(|c|{
+                units+=c.len_utf16();
+                units<=position.column
+            })
+            .count();
+        Position{column,..position}
+    }
+    /// Asks the server to shut down and waits a moment for it to agree, which it has to before it
+    /// is told to exit.
+    fn shutdown(&mut self)->IoResult<()> {
+        let id=self.next_id;
+        self.next_id+=1;
+        self.send(Json::object([
+            ("jsonrpc","2.0".into()),
+            ("id",id.into()),
+            ("method","shutdown".into()),
+        ]))?;
+        let deadline=Instant::now()+SHUTDOWN_TIMEOUT;
+        while let Some(left)=deadline.checked_duration_since(Instant::now()) {
+            match self.receiver.recv_timeout(left) {
+                Ok(Ok(message)) if message.get("method").is_null()&&message.get("id").as_usize()==Some(id)=>return Ok(()),
+                Ok(Ok(_))=>{},
+                Ok(Err(e))=>return Err(IoError::new(ErrorKind::BrokenPipe,e)),
+                Err(_)=>break,
+            }
+        }
+        Err(IoError::new(ErrorKind::TimedOut,"the server did not answer `shutdown`"))
+    }
+    fn request(&mut self,request:Request,method:&str,params:Json)->IoResult<()> {
+        let id=self.next_id;
+        self.next_id+=1;
+        self.pending.insert(id,request);
+        self.send(Json::object([
+            ("jsonrpc","2.0".into()),
+            ("id",id.into()),
+            ("method",method.into()),
+            ("params",params),
+        ]))
+    }
+    fn notify(&mut self,method:&str,params:Json)->IoResult<()> {
+        self.send(Json::object([
+            ("jsonrpc","2.0".into()),
+            ("method",method.into()),
+            ("params",params),
+        ]))
+    }
+    fn send(&mut self,message:Json)->IoResult<()> {
+        let body=message.to_string();
+        write!(self.stdin,"Content-Length: {}\r\n\r\n{}",body.len(),body)?;
+        self.stdin.flush()
+    }
+}
+impl Drop for Lsp {
+    fn drop(&mut self) {
+        if self.shutdown().is_ok()&&self.notify("exit",Json::Null).is_ok() {
+            // give the server a moment to exit on its own
+            let deadline=Instant::now()+SHUTDOWN_TIMEOUT;
+            while matches!(self.child.try_wait(),Ok(None))&&Instant::now()<deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let _=self.child.kill();
+        let _=self.child.wait();
+    }
+}
+
+
+/// The `file://` URI of an absolute path. Everything but unreserved characters and `/` is
+/// percent-encoded.
+fn file_uri(path:&Path)->String {
+    let mut uri="file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric()||b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}",byte));
+        }
+    }
+    uri
+}
+/// The path of a `file://` URI, with percent-encoding undone. Other URIs are returned as they
+/// are.
+pub fn uri_path(uri:&str)->String {
+    let path=match uri.strip_prefix("file://") {
+        Some(path)=>path.as_bytes(),
+        None=>return uri.to_string(),
+    };
+    let mut bytes=Vec::new();
+    let mut i=0;
+    while i<path.len() {
+        let escaped=path.get(i+1..i+3)
+            .and_then(|hex|std::str::from_utf8(hex).ok())
+            .and_then(|hex|u8::from_str_radix(hex,16).ok());
+        match (path[i],escaped) {
+            (b'%',Some(byte))=>{
+                bytes.push(byte);
+                i+=3;
+            },
+            (byte,_)=>{
+                bytes.push(byte);
+                i+=1;
+            },
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+
+/// Reads one message: headers up to an empty line, then a body as long as `Content-Length` says.
+fn read_message(from:&mut impl BufRead)->Result<Json,String> {
+    let mut length=None;
+    loop {
+        let mut line=String::new();
+        match from.read_line(&mut line) {
+            Ok(0)|Err(_)=>return Err("The language server closed".to_string()),
+            Ok(_)=>{},
+        }
+        let line=line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name,value))=line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length=value.trim().parse().ok();
+            }
+        }
+    }
+    let length=length.ok_or_else(||"A message from the language server had no length".to_string())?;
+    let mut body=vec![0;length];
+    from.read_exact(&mut body).map_err(|e|e.to_string())?;
+    Json::parse(&String::from_utf8_lossy(&body))
+}
+fn position(json:&Json)->Option<Position> {
+    Some(Position {
+        line:json.get("line").as_usize()?,
+        column:json.get("character").as_usize()?,
+    })
+}
+fn range(json:&Json)->Option<(Position,Position)> {
+    Some((position(json.get("start"))?,position(json.get("end"))?))
+}
+/// Reads a `Location` or a `LocationLink`.
+fn location(json:&Json)->Option<Location> {
+    let (uri,range)=match json.get("targetUri").as_str() {
+        Some(uri)=>(uri,json.get("targetSelectionRange")),
+        None=>(json.get("uri").as_str()?,json.get("range")),
+    };
+    Some(Location {
+        uri:uri.to_string(),
+        start:position(range.get("start"))?,
+    })
+}
+/// The text of hover contents, which can be a string, a `MarkupContent`, a `MarkedString` or a
+/// list of them.
+fn hover_text(contents:&Json)->String {
+    match contents {
+        Json::String(text)=>text.clone(),
+        Json::Array(items)=>items.iter()
+            .map(hover_text)
+            .filter(|text|!text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        contents=>contents.get("value").as_str().unwrap_or("").to_string(),
+    }
+}
+fn text_edits(edits:&Json)->Vec<TextEdit> {
+    edits.as_array().iter()
+        .filter_map(|edit|{
+            let (start,end)=range(edit.get("range"))?;
+            Some(TextEdit{start,end,text:edit.get("newText").as_str()?.to_string()})
+        })
+        .collect()
+}
+/// The edits in a `WorkspaceEdit`, which are either in `changes` or `documentChanges`.
+fn workspace_edit(edit:&Json)->Vec<(String,Vec<TextEdit>)> {
+    let mut files=Vec::new();
+    if let Json::Object(changes)=edit.get("changes") {
+        for (uri,edits) in changes {
+            files.push((uri.clone(),text_edits(edits)));
+        }
+    }
+    for change in edit.get("documentChanges").as_array() {
+        if let Some(uri)=change.get("textDocument").get("uri").as_str() {
+            files.push((uri.to_string(),text_edits(change.get("edits"))));
+        }
+    }
+    files
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::mpsc::Sender,
+    };
+    use super::*;
+
+    /// A client for `text` whose server is `cat`, with messages from the server coming from the
+    /// returned sender. Dropping the sender first lets the client shut down without waiting.
+    fn client(text:&str)->(Lsp,Sender<Result<Json,String>>) {
+        let mut child=Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        let stdin=child.stdin.take().unwrap();
+        let (sender,receiver)=channel();
+        let lsp=Lsp {
+            name:"cat".to_string(),
+            child,
+            stdin,
+            receiver,
+            next_id:0,
+            pending:HashMap::new(),
+            uri:"file:///tmp/my%20code.clj".to_string(),
+            language:"clojure",
+            version:1,
+            text:text.to_string(),
+            opened:true,
+        };
+        (lsp,sender)
+    }
+    fn at(line:usize,column:usize)->Position {
+        Position{line,column}
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let first=r#"{"id":1,"result":"é"}"#;
+        let second=r#"{"method":"exit"}"#;
+        let input=format!(
+            "Content-Length: {}\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}content-length: {}\r\n\r\n{}",
+            first.len(),first,second.len(),second,
+        );
+        let mut input=Cursor::new(input.into_bytes());
+        assert_eq!(read_message(&mut input).unwrap().get("result").as_str(),Some("é"));
+        assert_eq!(read_message(&mut input).unwrap().get("method").as_str(),Some("exit"));
+        assert!(read_message(&mut input).is_err());
+        assert!(read_message(&mut Cursor::new(b"Content-Type: x\r\n\r\n{}".to_vec())).is_err());
+    }
+    #[test]
+    fn utf16_columns() {
+        let (lsp,sender)=client("(a 😀 b)\n(é 😀)");
+        drop(sender);
+        assert_eq!(lsp.server_position(at(0,3)),at(0,3));
+        assert_eq!(lsp.server_position(at(0,5)),at(0,6));
+        assert_eq!(lsp.server_position(at(1,4)),at(1,5));
+        assert_eq!(lsp.editor_position(at(0,6)),at(0,5));
+        assert_eq!(lsp.editor_position(at(1,5)),at(1,4));
+        // the middle of a surrogate pair is the char it belongs to
+        assert_eq!(lsp.editor_position(at(0,4)),at(0,3));
+    }
+    #[test]
+    fn diagnostics() {
+        let (mut lsp,sender)=client("(a 😀 b)");
+        sender.send(Json::parse(r#"{"method":"textDocument/publishDiagnostics","params":{
+            "uri":"file:///tmp/my code.clj",
+            "diagnostics":[
+                {"range":{"start":{"line":0,"character":6},"end":{"line":0,"character":7}},"severity":2,"message":"unused"},
+                {"range":{"start":{"line":0,"character":0},"end":{"line":0,"character":1}},"message":"bad"}
+            ]
+        }}"#)).unwrap();
+        sender.send(Json::parse(r#"{"method":"textDocument/publishDiagnostics","params":{"uri":"file:///tmp/other.clj","diagnostics":[]}}"#)).unwrap();
+        drop(sender);
+        assert_eq!(lsp.poll().unwrap(),vec![LspEvent::Diagnostics(vec![
+            (at(0,5),at(0,6),Severity::Warning,"unused".to_string()),
+            (at(0,0),at(0,1),Severity::Error,"bad".to_string()),
+        ])]);
+    }
+    #[test]
+    fn definitions_and_renames() {
+        let (mut lsp,sender)=client("(a 😀 b)");
+        lsp.definition(at(0,5)).unwrap();
+        lsp.rename(at(0,5),"c").unwrap();
+        sender.send(Json::parse(r#"{"id":0,"result":[
+            {"uri":"file:///tmp/my%20code.clj","range":{"start":{"line":0,"character":6},"end":{"line":0,"character":7}}},
+            {"targetUri":"file:///tmp/other.clj","targetSelectionRange":{"start":{"line":2,"character":1},"end":{"line":2,"character":2}}}
+        ]}"#)).unwrap();
+        sender.send(Json::parse(r#"{"id":1,"result":{
+            "changes":{"file:///tmp/my%20code.clj":[{"range":{"start":{"line":0,"character":6},"end":{"line":0,"character":7}},"newText":"c"}]},
+            "documentChanges":[{"textDocument":{"uri":"file:///tmp/other.clj"},"edits":[{"range":{"start":{"line":2,"character":1},"end":{"line":2,"character":2}},"newText":"c"}]}]
+        }}"#)).unwrap();
+        drop(sender);
+        assert_eq!(lsp.poll().unwrap(),vec![
+            LspEvent::Definition(vec![
+                Location{uri:lsp.uri.clone(),start:at(0,5)},
+                Location{uri:"file:///tmp/other.clj".to_string(),start:at(2,1)},
+            ]),
+            LspEvent::Rename(vec![
+                (lsp.uri.clone(),vec![TextEdit{start:at(0,5),end:at(0,6),text:"c".to_string()}]),
+                ("file:///tmp/other.clj".to_string(),vec![TextEdit{start:at(2,1),end:at(2,2),text:"c".to_string()}]),
+            ]),
+        ]);
+    }
+    #[test]
+    fn file_uris() {
+        let uri=file_uri(Path::new("/home/me/my project#2/ü.scm"));
+        assert_eq!(uri,"file:///home/me/my%20project%232/%C3%BC.scm");
+        assert_eq!(uri_path(&uri),"/home/me/my project#2/ü.scm");
+        assert_eq!(uri_path("untitled:1"),"untitled:1");
+    }
+}
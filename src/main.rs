@@ -1,6 +1,3 @@
-use s_expression_parser::{
-    File as SFile,
-};
 use crossterm::{
     terminal::{
         EnterAlternateScreen,
@@ -10,8 +7,15 @@ use crossterm::{
     },
     event::{
         read as read_event,
+        poll as poll_event,
+        EnableMouseCapture,
+        DisableMouseCapture,
+        EnableBracketedPaste,
+        DisableBracketedPaste,
         Event,
         KeyCode,
+        MouseEventKind,
+        MouseButton,
     },
     cursor::{
         Show as ShowCursor,
@@ -22,31 +26,96 @@ use crossterm::{
 };
 use tui::{
     style::Style,
-    widgets::Paragraph,
+    widgets::{
+        Paragraph,
+        Wrap,
+        Block,
+        Borders,
+    },
     backend::CrosstermBackend,
     Terminal,
 };
 use std::{
     fmt::{
-        Write,
         Display,
         Formatter,
         Result as FmtResult,
     },
     fs::{
-        write as write_file,
         read_to_string,
+        remove_file,
+    },
+    io::{
+        Read,
+        Write,
+        ErrorKind,
+        stdout,
+        stdin,
+    },
+    time::{
+        Duration,
+        Instant,
     },
     mem::swap,
-    env::args,
-    io::stdout,
+    process::{
+        Command as ProcessCommand,
+        Stdio,
+        exit,
+    },
+    path::Path,
+    thread::{
+        spawn,
+        sleep,
+    },
+    sync::mpsc::{
+        Receiver,
+        channel,
+    },
+};
+use clap::{
+    Command,
+    Arg,
+    value_parser,
 };
 use object::*;
 use colors::*;
+use swap::*;
+use save::*;
+use options::*;
+use repl::*;
+use lsp::*;
 
 
 mod object;
 mod colors;
+mod swap;
+mod save;
+mod options;
+mod fmt;
+mod repl;
+mod nrepl;
+mod json;
+mod lsp;
+mod clipboard;
+
+
+/// The smallest terminal the editor will draw into. Anything smaller just shows a message.
+const MIN_WIDTH:u16=20;
+#[cfg(not(debug_assertions))]
+const MIN_HEIGHT:u16=3;
+/// Debug builds split the screen with the debug log, so they need a little more room.
+#[cfg(debug_assertions)]
+const MIN_HEIGHT:u16=4;
+/// Two clicks on the same cell within this time are a double click.
+const DOUBLE_CLICK:Duration=Duration::from_millis(400);
+/// How many lines one step of the scroll wheel moves the view.
+const SCROLL_LINES:isize=3;
+/// How long a `:!` command may run before it is killed.
+const FILTER_TIMEOUT:Duration=Duration::from_secs(10);
+/// The height of the REPL output pane, including its title.
+const REPL_HEIGHT:u16=8;
+const NO_REPL:&str="No REPL is running. Start one with `:repl <COMMAND>` or `:nrepl <PORT>`";
+const NO_LSP:&str="No language server is running. Start one with `:lsp [COMMAND]`";
 
 
 enum Mode {
@@ -167,16 +236,49 @@ macro_rules! gen_list {
 
 
 fn main() {
-    let mut filename;
-    let mut contents=if let Some(name)=args().skip(1).next() {
-        filename=Some(name);
-        let file_contents=read_to_string(filename.as_ref().unwrap()).unwrap();
-        let file=SFile::parse_file(&file_contents).unwrap();
-        file.items.into_iter().map(|o|o.into()).collect()
+    let matches=Command::new("sexpression_editor")
+        .about("A structural editor for s-expressions")
+        .args_conflicts_with_subcommands(true)
+        .arg(Arg::new("file")
+            .value_parser(value_parser!(String))
+            .help("The file to edit"))
+        .subcommand(fmt::command())
+        .get_matches();
+    if let Some(fmt_matches)=matches.subcommand_matches("fmt") {
+        exit(fmt::run(fmt_matches));
+    }
+    let mut filename=matches.get_one::<String>("file").cloned();
+    let mut saved=true;
+    let mut original=None;
+    let mut options=Options::default();
+    let mut command=String::new();
+    let mut contents=if let Some(name)=&filename {
+        options.set_dialect(Dialect::for_file(name));
+        // a swap file can outlive its file, so a file that is gone starts out empty
+        let read=||match read_to_string(name) {
+            Err(e) if e.kind()==ErrorKind::NotFound=>String::new(),
+            contents=>contents.unwrap(),
+        };
+        let file_contents=match ask_recover(name,options.dialect) {
+            Ok(Some(recovered))=>{
+                saved=false;
+                recovered
+            },
+            Ok(None)=>read(),
+            Err(e)=>{
+                command=e;
+                read()
+            },
+        };
+        let objects=Object::parse_file(&file_contents,options.dialect).unwrap();
+        original=Some(Original::new(file_contents,&objects));
+        objects
     } else {
-        filename=None;
         Vec::new()
     };
+    let mut swap_file=SwapFile::new(filename.as_deref());
+    let mut disk_stamp=filename.as_deref().and_then(FileStamp::read);
+    let mut disk_warned=false;
     let mut term=Terminal::new(CrosstermBackend::new(stdout())).unwrap();
     let mut cursor=vec![0];
     let colors=Colors::default();
@@ -184,25 +286,172 @@ fn main() {
     #[cfg(debug_assertions)]
     let mut debug_log:Vec<String>=Vec::new();
     let mut changed=true;
-    let mut saved=true;
-    let mut command=String::new();
     let mut command_cursor=1;
-    execute!(term.backend_mut(),EnterAlternateScreen).unwrap();
+    let mut view=View::default();
+    let mut selection:Option<usize>=None;
+    let mut last_click:Option<(Instant,u16,u16)>=None;
+    let mut drag_start:Option<Vec<usize>>=None;
+    let mut repl:Option<Repl>=None;
+    // leaving an invalid atom was refused once, so the next try goes through
+    let mut force_leave=false;
+    // a paste that couldn't be read, waiting to be told whether to insert it as a string
+    let mut pending_paste:Option<String>=None;
+    // the text of the last yanked objects
+    let mut yanked:Option<String>=None;
+    let mut lsp:Option<Lsp>=None;
+    let mut diagnostics:Vec<Diagnostic>=Vec::new();
+    // what the linter found along with what the language server found, in the order of the tree
+    let mut problems:Vec<Diagnostic>=Vec::new();
+    execute!(term.backend_mut(),EnterAlternateScreen,EnableMouseCapture,EnableBracketedPaste).unwrap();
     enable_raw_mode().unwrap();
     'main:loop {
+        if let Some(running)=&mut repl {
+            changed|=running.poll();
+            if !running.is_running() {
+                command=format!("The REPL `{}` exited",running.name);
+                repl=None;
+                changed=true;
+            }
+        }
+        if let Some(server)=&mut lsp {
+            match server.poll() {
+                Ok(events) if !events.is_empty()=>{
+                    // the server's positions are in the text it was sent
+                    let map=options.source_map(&contents,original.as_ref());
+                    for event in events {
+                        match event {
+                            LspEvent::Diagnostics(found)=>{
+                                diagnostics=found.into_iter()
+                                    .filter_map(|(start,_,severity,message)|Some(Diagnostic {
+                                        path:map.path_at_position(start)?.to_vec(),
+                                        severity,
+                                        message,
+                                    }))
+                                    .collect();
+                            },
+                            LspEvent::Hover(text)=>command=match text.trim() {
+                                ""=>"Nothing to show here".to_string(),
+                                text=>text.lines().map(str::trim).filter(|line|!line.is_empty()).collect::<Vec<_>>().join(" "),
+                            },
+                            LspEvent::Definition(locations)=>match locations.first() {
+                                Some(location) if location.uri==server.uri=>if let Some(path)=map.path_at_position(location.start) {
+                                    cursor=path.to_vec();
+                                    #[cfg(debug_assertions)]
+                                    {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                    #[cfg(not(debug_assertions))]
+                                    {mode=make_valid(&mut cursor,&contents)}
+                                    view.follow_cursor=true;
+                                },
+                                Some(location)=>command=format!("Defined in {} on line {}",
+                                    uri_path(&location.uri),
+                                    location.start.line+1,
+                                ),
+                                None=>command="No definition found".to_string(),
+                            },
+                            LspEvent::Rename(files)=>{
+                                // only symbols in this buffer can be renamed, and only when the
+                                // edit covers the whole symbol
+                                let mut applied=0;
+                                let mut skipped=0;
+                                for (uri,edits) in files {
+                                    if uri!=server.uri {
+                                        skipped+=edits.len();
+                                        continue;
+                                    }
+                                    for edit in edits {
+                                        let span=map.spans.iter()
+                                            .find(|span|span.start==edit.start&&span.end==edit.end);
+                                        match span.and_then(|span|object_at_mut(&mut contents,&span.path)) {
+                                            Some(symbol@Object::Ident(..))=>{
+                                                symbol.set_text(&edit.text);
+                                                applied+=1;
+                                            },
+                                            _=>skipped+=1,
+                                        }
+                                    }
+                                }
+                                if applied>0 {
+                                    saved=false;
+                                }
+                                command=if skipped>0 {
+                                    format!("Renamed {} symbols. {} edits could not be applied",applied,skipped)
+                                } else {
+                                    format!("Renamed {} symbols",applied)
+                                };
+                            },
+                            LspEvent::Message(text)=>command=text,
+                        }
+                    }
+                    changed=true;
+                },
+                Ok(_)=>{},
+                Err(e)=>{
+                    command=format!("Could not answer the language server. Reason: {}",e);
+                    changed=true;
+                },
+            }
+            if !server.is_running() {
+                command=format!("The language server `{}` exited",server.name);
+                lsp=None;
+                diagnostics.clear();
+                changed=true;
+            }
+        }
         if changed {
             #[cfg(debug_assertions)]
             debug_log.push(format!("Command cursor: {}; Item length: {}; Mode: {}; Cursor: {}:{:?}",command_cursor,mode.len(),mode,cursor[0],&cursor[1..]));
+            if !saved {
+                if let Err(e)=swap_file.update(options.serialize(&contents,original.as_ref())) {
+                    command=format!("Could not write the swap file. Reason: {}",e);
+                }
+            }
+            problems=if options.lint {lint(&contents,options.dialect)} else {Vec::new()};
+            problems.extend(diagnostics.iter().cloned());
+            problems.sort_by(|a,b|a.path.cmp(&b.path));
+            if let Some(server)=&mut lsp {
+                if let Err(e)=server.sync(options.source_map(&contents,original.as_ref()).text) {
+                    command=format!("Could not update the language server. Reason: {}",e);
+                }
+            }
             let mut y=0;
             term.draw(|f|{
                 let mut size=f.size();
+                if size.width<MIN_WIDTH||size.height<MIN_HEIGHT {
+                    f.render_widget(
+                        Paragraph::new("Terminal too small")
+                            .wrap(Wrap{trim:true}),
+                        size,
+                    );
+                    return;
+                }
                 size.height-=2;
                 #[cfg(debug_assertions)]
                 {size.height/=2}
+                // the REPL output takes the bottom of the object area once there is any
+                // the REPL output takes the bottom of the object area once there is any. A pane
+                // without room for a line below its border is left out
+                let mut objects_area=size;
+                let mut pane=size;
+                pane.height=REPL_HEIGHT.min(size.height/2);
+                let repl_pane=repl.as_ref()
+                    .filter(|repl|repl.lines.len()>1||!repl.lines[0].is_empty())
+                    .filter(|_|pane.height>=2);
+                if repl_pane.is_some() {
+                    objects_area.height-=pane.height;
+                    pane.y+=objects_area.height;
+                }
                 if !mode.is_command() {
-                    f.render_widget(ObjectWidget::new(&contents,&colors,&cursor),size);
+                    f.render_stateful_widget(ObjectWidget::new(&contents,&colors,&cursor).selection(selection).format(&options.format).dialect(options.dialect).dim(options.dim).diagnostics(&problems).gutter(options.lint||lsp.is_some()),objects_area,&mut view);
                 } else {
-                    f.render_widget(ObjectWidget::new(&contents,&colors,&[]),size);
+                    f.render_stateful_widget(ObjectWidget::new(&contents,&colors,&[]).selection(selection).format(&options.format).dialect(options.dialect).dim(options.dim).diagnostics(&problems).gutter(options.lint||lsp.is_some()),objects_area,&mut view);
+                }
+                if let Some(repl)=repl_pane {
+                    let shown=repl.lines.len().saturating_sub(pane.height as usize-1);
+                    f.render_widget(
+                        Paragraph::new(repl.lines[shown..].join("\n"))
+                            .block(Block::default().borders(Borders::TOP).title(repl.name.as_str())),
+                        pane,
+                    );
                 }
                 #[cfg(debug_assertions)]
                 {
@@ -233,22 +482,195 @@ fn main() {
                         format!("{}{:>2$}",
                             left,
                             right_size,
-                            (size.width-2) as usize-left.chars().count()
+                            (size.width as usize-2).saturating_sub(left.chars().count())
                         )
                     ).style(Style::reset().bg(colors.statusline)),
                     size,
                 );
                 size.y+=1;
                 y=size.y;
-                f.render_widget(Paragraph::new(command.as_str()),size);
+                // with nothing else to say, show the problem under the cursor
+                match Diagnostic::at(&problems,&cursor).filter(|_|command.is_empty()&&!mode.is_command()) {
+                    Some(diagnostic)=>f.render_widget(
+                        Paragraph::new(diagnostic.message.lines().next().unwrap_or(""))
+                            .style(Style::reset().fg(diagnostic.severity.color(&colors))),
+                        size,
+                    ),
+                    None=>f.render_widget(Paragraph::new(command.as_str()),size),
+                }
             }).unwrap();
             if mode.is_command() {
                 execute!(term.backend_mut(),ShowCursor,MoveCursorTo(command_cursor as u16,y)).unwrap();
             }
             changed=false;
         }
+        // check often while a REPL might be printing or a language server answering
+        let timeout=if repl.is_some()||lsp.is_some() {100} else {500};
+        if !poll_event(Duration::from_millis(timeout)).unwrap() {
+            // nothing happened, so check if another program changed the file
+            if let Some(filename)=&filename {
+                if !disk_warned&&!mode.is_command()&&FileStamp::read(filename)!=disk_stamp {
+                    command=format!("`{}` changed on disk. Reload with `:e!` or overwrite with `:w!`",filename);
+                    disk_warned=true;
+                    changed=true;
+                }
+            }
+            continue;
+        }
         match read_event().unwrap() {
+            Event::Resize(_,_)=>{
+                // throw away whatever the terminal left on screen and lay everything out again
+                term.autoresize().unwrap();
+                term.clear().unwrap();
+                changed=true;
+            },
+            Event::Paste(text)=>{
+                // terminals send line breaks as carriage returns
+                let text=text.replace("\r\n","\n").replace('\r',"\n");
+                if mode.is_command() {
+                    // the command line is a single line
+                    let text=text.replace('\n'," ");
+                    command.insert_str(command_cursor,&text);
+                    command_cursor+=text.len();
+                } else if mode.is_edit() {
+                    let rejected=object_at(&contents,&cursor[..cursor.len()-1])
+                        .and_then(|atom|Some((text.chars().find(|c|!atom.accepts(*c))?,atom.kind())));
+                    if let Some((c,kind))=rejected {
+                        command=format!("Could not paste into the {}: `{}` can't be part of one",kind,c.escape_default());
+                    } else {
+                        for c in text.chars() {
+                            contents[cursor[0]].add_char(&cursor[1..],c);
+                            *cursor.last_mut().unwrap()+=1;
+                            mode.add(1);
+                        }
+                        saved=false;
+                    }
+                } else {
+                    selection=None;
+                    match Object::parse_snippet(&text,options.dialect) {
+                        Ok(objects) if objects.is_empty()=>command="Nothing to paste".to_string(),
+                        Ok(objects)=>{
+                            insert_objects(&mut contents,&mut cursor,mode.len(),objects);
+                            #[cfg(debug_assertions)]
+                            {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                            #[cfg(not(debug_assertions))]
+                            {mode=make_valid(&mut cursor,&contents)}
+                            saved=false;
+                        },
+                        Err(e)=>{
+                            command=format!("Could not read the paste. Reason: {}. Press `y` to insert it as a string",e);
+                            pending_paste=Some(text);
+                        },
+                    }
+                }
+                changed=true;
+            },
+            Event::Mouse(event) if !mode.is_command()=>{
+                // moving the mouse and letting go of the first click don't answer the question of
+                // whether to leave anyway, while another click does
+                let force=match event.kind {
+                    MouseEventKind::Down(_)=>std::mem::take(&mut force_leave),
+                    MouseEventKind::Moved|MouseEventKind::Up(_)=>true,
+                    _=>{
+                        force_leave=false;
+                        true
+                    },
+                };
+                match event.kind {
+                    MouseEventKind::ScrollUp=>{
+                        view.scroll_by(-SCROLL_LINES);
+                        changed=true;
+                    },
+                    MouseEventKind::ScrollDown=>{
+                        view.scroll_by(SCROLL_LINES);
+                        changed=true;
+                    },
+                    MouseEventKind::Down(MouseButton::Left)=>if let Some(problem)=editing_problem(&contents,&cursor,&mode).filter(|_|!force) {
+                        command=format!("{}. Fix it, or click again to leave it anyway",problem);
+                        force_leave=true;
+                        changed=true;
+                    } else if let Some(node)=view.node_at(event.column,event.row) {
+                        let double_click=matches!(
+                            last_click,
+                            Some((time,column,row)) if time.elapsed()<DOUBLE_CLICK&&column==event.column&&row==event.row
+                        );
+                        last_click=Some((Instant::now(),event.column,event.row));
+                        cursor=node.path.clone();
+                        selection=None;
+                        drag_start=Some(node.path.clone());
+                        if double_click {
+                            // go into the node: lists move to their first item, atoms start editing
+                            // at the clicked char
+                            match object_at(&contents,&cursor) {
+                                Some(Object::List(..))=>cursor.push(0),
+                                Some(Object::String(..))=>cursor.push(view.column_in(node,event.column).saturating_sub(1)),
+                                _=>cursor.push(view.column_in(node,event.column)),
+                            }
+                            drag_start=None;
+                        }
+                        #[cfg(debug_assertions)]
+                        {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                        #[cfg(not(debug_assertions))]
+                        {mode=make_valid(&mut cursor,&contents)}
+                        changed=true;
+                    },
+                    MouseEventKind::Drag(MouseButton::Left)=>if let (Some(start),Some(node))=(&drag_start,view.node_at(event.column,event.row)) {
+                        // select the siblings between the two nodes, or their ancestors that share
+                        // a parent
+                        let common=start.iter()
+                            .zip(node.path.iter())
+                            .take_while(|(a,b)|a==b)
+                            .count();
+                        if common<start.len()&&common<node.path.len() {
+                            cursor=node.path[..=common].to_vec();
+                            selection=Some(start[common]);
+                        } else {
+                            cursor=node.path[..common].to_vec();
+                            selection=None;
+                        }
+                        #[cfg(debug_assertions)]
+                        {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                        #[cfg(not(debug_assertions))]
+                        {mode=make_valid(&mut cursor,&contents)}
+                        changed=true;
+                    },
+                    MouseEventKind::Up(MouseButton::Left)=>drag_start=None,
+                    _=>{},
+                }
+            },
             Event::Key(event)=>{
+                view.follow_cursor=true;
+                let force=std::mem::take(&mut force_leave);
+                if let Some(text)=pending_paste.take() {
+                    if event.code==KeyCode::Char('y') {
+                        // a double click may have started editing an atom since, so the string goes
+                        // after that atom
+                        if mode.is_edit() {
+                            mode.set_structure();
+                            if cursor.len()>1 {
+                                cursor.pop();
+                            }
+                            #[cfg(debug_assertions)]
+                            {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                            #[cfg(not(debug_assertions))]
+                            {mode=make_valid(&mut cursor,&contents)}
+                        }
+                        insert_objects(&mut contents,&mut cursor,mode.len(),vec![Object::String(text,None)]);
+                        #[cfg(debug_assertions)]
+                        {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                        #[cfg(not(debug_assertions))]
+                        {mode=make_valid(&mut cursor,&contents)}
+                        command=String::new();
+                        saved=false;
+                    } else {
+                        command="Dropped the paste".to_string();
+                    }
+                    changed=true;
+                    continue;
+                }
+                if !mode.is_command()&&event.code!=KeyCode::Delete&&event.code!=KeyCode::Char(':')&&event.code!=KeyCode::Char('y')&&selection.take().is_some() {
+                    changed=true;
+                }
                 // if !mode.is_command() {
                 //     command=format!("Event: {:?}",event);
                 // }
@@ -256,10 +678,15 @@ fn main() {
                     // Movement
                     KeyCode::Esc=>if mode.is_command() {
                         command_cursor=1;
+                        selection=None;
                         command=String::new();
                         execute!(term.backend_mut(),HideCursor).unwrap();
                         mode.set_structure();
                         changed=true;
+                    } else if let Some(problem)=editing_problem(&contents,&cursor,&mode).filter(|_|!force) {
+                        command=format!("{}. Fix it, or press Esc again to leave it anyway",problem);
+                        force_leave=true;
+                        changed=true;
                     } else {
                         mode.set_structure();
                         if cursor.len()>1 {
@@ -332,12 +759,21 @@ fn main() {
                         }
                     },
                     // Editing an object
-                    KeyCode::Char(c) if mode.is_edit()=>{
-                        contents[cursor[0]].add_char(&cursor[1..],c);
-                        *cursor.last_mut().unwrap()+=1;
-                        mode.add(1);
+                    // Enter puts a newline in strings, which are edited as their value
+                    KeyCode::Char(_)|KeyCode::Enter if mode.is_edit()=>{
+                        let c=match event.code {
+                            KeyCode::Char(c)=>c,
+                            _=>'\n',
+                        };
+                        if let Some(atom)=object_at(&contents,&cursor[..cursor.len()-1]).filter(|atom|!atom.accepts(c)) {
+                            command=format!("`{}` can't be part of a {}",c.escape_default(),atom.kind());
+                        } else {
+                            contents[cursor[0]].add_char(&cursor[1..],c);
+                            *cursor.last_mut().unwrap()+=1;
+                            mode.add(1);
+                            saved=false;
+                        }
                         changed=true;
-                        saved=false;
                     },
                     KeyCode::Backspace if mode.is_edit()=>{
                         if *cursor.last().unwrap()>0&&mode.len()>0 {
@@ -356,7 +792,26 @@ fn main() {
                             saved=false;
                         }
                     } else if mode.is_structural() {
-                        if mode.len()>0 {
+                        if let Some(anchor)=selection.take() {
+                            let last=*cursor.last().unwrap();
+                            let (first,last)=(anchor.min(last),anchor.max(last));
+                            if last<mode.len() {
+                                *cursor.last_mut().unwrap()=first;
+                                for _ in first..=last {
+                                    if cursor.len()==1 {
+                                        contents.remove(first);
+                                    } else {
+                                        contents[cursor[0]].remove(&cursor[1..]);
+                                    }
+                                }
+                                #[cfg(debug_assertions)]
+                                {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                #[cfg(not(debug_assertions))]
+                                {mode=make_valid(&mut cursor,&contents)}
+                                changed=true;
+                                saved=false;
+                            }
+                        } else if mode.len()>0 {
                             if cursor.len()==1 {
                                 if cursor[0]<contents.len() {
                                     contents.remove(cursor[0]);
@@ -420,26 +875,75 @@ fn main() {
                         swap(&mut c,&mut command);
                         let args=c[1..].split(' ').collect::<Vec<_>>();
                         match args[0] {
-                            "q"|"wq"|"q!"|"wq!"|"w"=>{
+                            // writing part of the buffer, which leaves the buffer and its file name alone
+                            ".w"|".w!"|"w"|"w!" if args[0].starts_with('.')||(selection.is_some()&&args.len()>1)=>{
+                                let objects=selected(&contents,&cursor,selection);
+                                match args.get(1) {
+                                    None=>command="Give the file to write to".to_string(),
+                                    Some(_) if objects.is_empty()=>command="There is nothing to write here".to_string(),
+                                    Some(path) if !args[0].ends_with('!')&&Path::new(path).exists()=>{
+                                        command=format!("`{}` already exists. Overwrite it with `:{}! {}`",path,args[0],path);
+                                    },
+                                    Some(path)=>{
+                                        let out=serialize(objects,&options.format);
+                                        if let Err(e)=write_atomic(path,&out,options.backup) {
+                                            command=format!("Could not write `{}`. Reason: {}",path,e);
+                                        } else {
+                                            command=format!("Wrote {} objects to `{}` {} lines, {} bytes",objects.len(),path,out.lines().count(),out.len());
+                                        }
+                                    },
+                                }
+                            },
+                            "r"=>match args.get(1) {
+                                None=>command="Give the file to read".to_string(),
+                                Some(path)=>match read_to_string(path).map_err(|e|e.to_string()).and_then(|s|Object::parse_snippet(&s,options.dialect)) {
+                                    Ok(objects) if objects.is_empty()=>command=format!("`{}` has nothing to insert",path),
+                                    Ok(objects)=>{
+                                        command=format!("Inserted {} objects from `{}`",objects.len(),path);
+                                        insert_objects(&mut contents,&mut cursor,mode.len(),objects);
+                                        #[cfg(debug_assertions)]
+                                        {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                        #[cfg(not(debug_assertions))]
+                                        {mode=make_valid(&mut cursor,&contents)}
+                                        saved=false;
+                                    },
+                                    Err(e)=>command=format!("Could not read `{}`. Reason: {}",path,e),
+                                },
+                            },
+                            "q"|"wq"|"q!"|"wq!"|"w"|"w!"=>{
                                 let force=args[0].contains('!');
-                                let quit=args[0].contains('q');
-                                let write=args[0].contains('w');
+                                let mut quit=args[0].contains('q');
+                                let mut write=args[0].contains('w');
+                                // saving under another name must not clobber a file that is already there
+                                if write&&!force&&args.len()>1&&filename.as_deref()!=Some(args[1])&&Path::new(args[1]).exists() {
+                                    command=format!("`{}` already exists. Overwrite it with `:{}! {}`",args[1],args[0],args[1]);
+                                    write=false;
+                                    quit=false;
+                                }
                                 if write {
                                     if args.len()>1 {
                                         filename=Some(args[1].to_string());
+                                        swap_file.set_filename(filename.as_deref());
+                                        disk_stamp=filename.as_deref().and_then(FileStamp::read);
                                     }
                                     if let Some(filename)=&filename {
-                                        let mut out=String::new();
-                                        for obj in contents.iter() {
-                                            writeln!(out,"{}",obj).unwrap();
-                                        }
-                                        let lines=out.lines().count();
-                                        let bytes=out.len();
-                                        if let Err(e)=write_file(&filename,out) {
-                                            command=format!("Could not save file. Reason: {}",e);
+                                        if !force&&FileStamp::read(filename)!=disk_stamp {
+                                            command=format!("`{}` changed on disk since it was read. Overwrite with `:w!` or reload with `:e!`",filename);
+                                            disk_warned=true;
                                         } else {
-                                            command=format!("`{}` {} lines, {} bytes",filename,lines,bytes);
-                                            saved=true;
+                                            let out=options.serialize(&contents,original.as_ref());
+                                            let lines=out.lines().count();
+                                            let bytes=out.len();
+                                            if let Err(e)=write_atomic(filename,&out,options.backup) {
+                                                command=format!("Could not save file. Reason: {}",e);
+                                            } else {
+                                                command=format!("`{}` {} lines, {} bytes",filename,lines,bytes);
+                                                saved=true;
+                                                swap_file.remove();
+                                                disk_stamp=FileStamp::read(filename);
+                                                disk_warned=false;
+                                                original=Some(Original::saved(out,&mut contents,options.dialect));
+                                            }
                                         }
                                     } else {
                                         command=format!("No file name");
@@ -457,17 +961,286 @@ fn main() {
                                     }
                                 }
                             },
+                            "e"|"e!"=>if let Some(filename)=&filename {
+                                if !saved&&args[0]!="e!" {
+                                    command="The buffer has unsaved changes. To throw them away and reload, do `:e!`".to_string();
+                                } else {
+                                    match read_to_string(filename).map_err(|e|e.to_string()).and_then(|s|Object::parse_file(&s,options.dialect).map(|o|(o,s))) {
+                                        Ok((objects,text))=>{
+                                            original=Some(Original::new(text,&objects));
+                                            contents=objects;
+                                            saved=true;
+                                            swap_file.remove();
+                                            disk_stamp=FileStamp::read(filename);
+                                            disk_warned=false;
+                                            // keep the cursor as close to its old path as the new tree allows
+                                            loop {
+                                                #[cfg(debug_assertions)]
+                                                {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                                #[cfg(not(debug_assertions))]
+                                                {mode=make_valid(&mut cursor,&contents)}
+                                                if mode.is_edit()&&cursor.len()>1 {
+                                                    cursor.pop();
+                                                } else {
+                                                    break;
+                                                }
+                                            }
+                                            command=format!("Reloaded `{}`",filename);
+                                        },
+                                        Err(e)=>command=format!("Could not reload file. Reason: {}",e),
+                                    }
+                                }
+                            } else {
+                                command="No file name".to_string();
+                            },
+                            "indent"=>match args.get(1) {
+                                Some(head)=>match args.get(2) {
+                                    Some(rule)=>match Indent::parse(rule) {
+                                        Some(rule)=>{
+                                            options.format.indent.forms.insert(head.to_string(),rule);
+                                        },
+                                        None=>command=format!("Unknown indent rule `{}`. Use `align`, `defn` or the amount of special arguments",rule),
+                                    },
+                                    None=>command=format!("`{}` is indented with {:?}",head,options.format.indent.rule(head)),
+                                },
+                                None=>command=format!("Indent style is `{}`",options.format.indent.name),
+                            },
+                            "repl"=>{
+                                let stopped=repl.take().is_some();
+                                if args.len()>1 {
+                                    let repl_command=args[1..].join(" ");
+                                    match Repl::start(&repl_command) {
+                                        Ok(started)=>{
+                                            command=format!("Started `{}`",repl_command);
+                                            repl=Some(started);
+                                        },
+                                        Err(e)=>command=format!("Could not start `{}`. Reason: {}",repl_command,e),
+                                    }
+                                } else if stopped {
+                                    command="Stopped the REPL".to_string();
+                                } else {
+                                    command=NO_REPL.to_string();
+                                }
+                            },
+                            "nrepl"=>{
+                                // nREPL servers write their port to `.nrepl-port` in the project
+                                let port=match args.get(1) {
+                                    Some(port)=>port.parse().ok(),
+                                    None=>read_to_string(".nrepl-port").ok().and_then(|port|port.trim().parse().ok()),
+                                };
+                                match port {
+                                    Some(port)=>match Repl::connect(port) {
+                                        Ok(connected)=>{
+                                            command=format!("Connected to nREPL on port {}",port);
+                                            repl=Some(connected);
+                                        },
+                                        Err(e)=>command=format!("Could not connect to port {}. Reason: {}",port,e),
+                                    },
+                                    None=>command="Give a port, or run this where the server wrote `.nrepl-port`".to_string(),
+                                }
+                            },
+                            "load"=>match (&mut repl,&filename) {
+                                (Some(repl),Some(filename))=>{
+                                    let text=options.serialize(&contents,original.as_ref());
+                                    if let Err(e)=repl.load_file(&text,filename) {
+                                        command=format!("Could not load the file. Reason: {}",e);
+                                    }
+                                },
+                                (None,_)=>command=NO_REPL.to_string(),
+                                (_,None)=>command="No file name".to_string(),
+                            },
+                            "interrupt"=>match &mut repl {
+                                Some(repl)=>if let Err(e)=repl.interrupt() {
+                                    command=format!("Could not interrupt. Reason: {}",e);
+                                },
+                                None=>command=NO_REPL.to_string(),
+                            },
+                            "complete"=>{
+                                // complete the given prefix, or the symbol under the cursor
+                                let prefix=match (args.get(1),object_at(&contents,&cursor)) {
+                                    (Some(prefix),_)=>Some(prefix.to_string()),
+                                    (None,Some(Object::Ident(symbol,_)))=>Some(symbol.clone()),
+                                    _=>None,
+                                };
+                                match (&mut repl,prefix) {
+                                    (None,_)=>command=NO_REPL.to_string(),
+                                    (_,None)=>command="There is no symbol to complete here".to_string(),
+                                    (Some(repl),Some(prefix))=>if let Err(e)=repl.complete(&prefix) {
+                                        command=format!("Could not complete. Reason: {}",e);
+                                    },
+                                }
+                            },
+                            "lsp"=>{
+                                let stopped=lsp.take().is_some();
+                                diagnostics.clear();
+                                // `:lsp` alone stops a running server or starts the one for the dialect
+                                let server_command=match args[1..].join(" ").trim() {
+                                    "" if stopped=>None,
+                                    ""=>options.dialect.language_server.map(str::to_string),
+                                    server_command=>Some(server_command.to_string()),
+                                };
+                                match (server_command,&filename) {
+                                    (Some(server_command),Some(filename))=>match Lsp::start(&server_command,filename,options.dialect.language_id) {
+                                        Ok(started)=>{
+                                            command=format!("Started `{}`",server_command);
+                                            lsp=Some(started);
+                                        },
+                                        Err(e)=>command=format!("Could not start `{}`. Reason: {}",server_command,e),
+                                    },
+                                    (Some(_),None)=>command="The buffer needs a file name for a language server".to_string(),
+                                    (None,_) if stopped=>command="Stopped the language server".to_string(),
+                                    (None,_)=>command=format!("There is no language server for {}. Give a command with `:lsp <COMMAND>`",options.dialect.name),
+                                }
+                            },
+                            "hover"|"def"|"rename"=>{
+                                let map=options.source_map(&contents,original.as_ref());
+                                let position=map.span(&cursor).map(|span|span.start);
+                                match (&mut lsp,position) {
+                                    (None,_)=>command=NO_LSP.to_string(),
+                                    (_,None)=>command="There is nothing here to ask about".to_string(),
+                                    (Some(server),Some(position))=>{
+                                        let sent=match (args[0],args.get(1)) {
+                                            ("hover",_)=>server.hover(position),
+                                            ("def",_)=>server.definition(position),
+                                            (_,Some(name)) if !name.is_empty()=>server.rename(position,name),
+                                            _=>{
+                                                command="Give the new name with `:rename <NAME>`".to_string();
+                                                Ok(())
+                                            },
+                                        };
+                                        if let Err(e)=sent {
+                                            command=format!("Could not ask the language server. Reason: {}",e);
+                                        }
+                                    },
+                                }
+                            },
+                            // filtering objects through a shell command, like a formatter
+                            _ if c.starts_with(":!")=>{
+                                let shell_command=c[2..].trim();
+                                let objects=selected(&contents,&cursor,selection);
+                                if shell_command.is_empty() {
+                                    command="Give the command to filter through, like `:!sort`".to_string();
+                                } else if objects.is_empty() {
+                                    command="There is nothing to filter here".to_string();
+                                } else {
+                                    let count=objects.len();
+                                    let input=serialize(objects,&options.format);
+                                    let result=run_filter(shell_command,input).and_then(|(output,stderr)|match Object::parse_snippet(&output,options.dialect) {
+                                        Ok(objects) if objects.is_empty()=>Err(format!("`{}` printed nothing. {}",shell_command,stderr)),
+                                        Ok(objects)=>Ok(objects),
+                                        Err(e)=>Err(format!("Could not read what `{}` printed. Reason: {}. {}",shell_command,e,stderr)),
+                                    });
+                                    match result {
+                                        Ok(objects)=>{
+                                            let last=*cursor.last().unwrap();
+                                            let first=selection.map_or(last,|anchor|anchor.min(last));
+                                            command=format!("Replaced {} objects with {}",count,objects.len());
+                                            replace_objects(&mut contents,&mut cursor,first,count,objects);
+                                            #[cfg(debug_assertions)]
+                                            {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                            #[cfg(not(debug_assertions))]
+                                            {mode=make_valid(&mut cursor,&contents)}
+                                            saved=false;
+                                        },
+                                        Err(e)=>command=e.trim().to_string(),
+                                    }
+                                }
+                            },
+                            "insert"=>{
+                                let text=c[1..].split_once(' ').map_or("",|(_,text)|text);
+                                match Object::parse_snippet(text,options.dialect) {
+                                    Ok(objects) if objects.is_empty()=>command="Nothing to insert".to_string(),
+                                    Ok(objects)=>{
+                                        insert_objects(&mut contents,&mut cursor,mode.len(),objects);
+                                        #[cfg(debug_assertions)]
+                                        {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                        #[cfg(not(debug_assertions))]
+                                        {mode=make_valid(&mut cursor,&contents)}
+                                        saved=false;
+                                    },
+                                    Err(e)=>command=format!("Could not read `{}`. Reason: {}",text,e),
+                                }
+                            },
+                            "convert"=>match (object_at(&contents,&cursor),args.get(1)) {
+                                (None,_)=>command="There is nothing to convert here".to_string(),
+                                (Some(_),None)=>command=format!("Give the kind to convert to: {}",KINDS.join(", ")),
+                                (Some(_),Some(kind))=>match convert_at(&mut contents,&cursor,kind) {
+                                    Ok(())=>{
+                                        #[cfg(debug_assertions)]
+                                        {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                        #[cfg(not(debug_assertions))]
+                                        {mode=make_valid(&mut cursor,&contents)}
+                                        saved=false;
+                                    },
+                                    Err(e)=>command=format!("Could not convert to a {}. {}",kind,e),
+                                },
+                            },
+                            "lint"=>if !options.lint&&lsp.is_none() {
+                                command="Linting is off. Turn it on with `:set lint`".to_string();
+                            } else {
+                                // jump to the next problem after the cursor, going around to the
+                                // first one after the last
+                                let next=problems.iter()
+                                    .position(|problem|problem.path>cursor)
+                                    .or(if problems.is_empty() {None} else {Some(0)});
+                                match next {
+                                    Some(i)=>{
+                                        cursor=problems[i].path.clone();
+                                        #[cfg(debug_assertions)]
+                                        {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                        #[cfg(not(debug_assertions))]
+                                        {mode=make_valid(&mut cursor,&contents)}
+                                        command=format!("Problem {} of {}: {}",i+1,problems.len(),problems[i].message.lines().next().unwrap_or(""));
+                                    },
+                                    None=>command="No problems found".to_string(),
+                                }
+                            },
+                            // lines count from 1, like `grep -n` and other editors do
+                            line if !line.is_empty()&&line.chars().all(|c|c.is_ascii_digit())=>{
+                                let map=options.source_map(&contents,original.as_ref());
+                                match line.parse::<usize>().ok().and_then(|line|map.path_at_line(line.saturating_sub(1))) {
+                                    Some(path)=>{
+                                        cursor=path.to_vec();
+                                        #[cfg(debug_assertions)]
+                                        {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                        #[cfg(not(debug_assertions))]
+                                        {mode=make_valid(&mut cursor,&contents)}
+                                    },
+                                    None=>command=format!("There is nothing on line {}",line),
+                                }
+                            },
+                            "set"=>{
+                                for arg in args[1..].iter().filter(|a|!a.is_empty()) {
+                                    if let Err(e)=options.set(arg) {
+                                        command=e;
+                                        break;
+                                    }
+                                }
+                            },
                             _=>{},
                         }
                         command_cursor=1;
+                        selection=None;
                         execute!(term.backend_mut(),HideCursor).unwrap();
                         mode.set_structure();
                         changed=true;
                     },
+                    // Evaluating the object under the cursor, or its top-level form
+                    KeyCode::Char('e')|KeyCode::Char('E') if mode.is_structural()=>{
+                        let path=if event.code==KeyCode::Char('E') {&cursor[..1]} else {&cursor[..]};
+                        match (&mut repl,object_at(&contents,path)) {
+                            (None,_)=>command=NO_REPL.to_string(),
+                            (Some(_),None)=>command="There is nothing to evaluate here".to_string(),
+                            (Some(repl),Some(object))=>if let Err(e)=repl.send(&object.to_string()) {
+                                command=format!("Could not send to the REPL. Reason: {}",e);
+                            },
+                        }
+                        changed=true;
+                    },
                     // Deleting an object
                     // Adding an object
                     KeyCode::Char('l') if mode.is_structural()=>{
-                        let obj=Object::List(Vec::new());
+                        let obj=Object::List(Vec::new(),Delimiter::Paren,None);
                         if mode.len()>0&&*cursor.last().unwrap()<mode.len() {*cursor.last_mut().unwrap()+=1}
                         if cursor.len()==1 {
                             if cursor[0]+1>contents.len() {
@@ -484,7 +1257,7 @@ fn main() {
                         saved=false;
                     },
                     KeyCode::Char('"')|KeyCode::Char('\'') if mode.is_structural()=>{
-                        let obj=Object::String(String::new());
+                        let obj=Object::String(String::new(),None);
                         if mode.len()>0&&*cursor.last().unwrap()<mode.len() {*cursor.last_mut().unwrap()+=1}
                         if cursor.len()==1 {
                             if cursor[0]>contents.len() {
@@ -501,8 +1274,52 @@ fn main() {
                         changed=true;
                         saved=false;
                     },
+                    // Copying objects as text, which is printed the way it is saved
+                    KeyCode::Char('y') if mode.is_structural()=>{
+                        let objects=selected(&contents,&cursor,selection.take());
+                        if objects.is_empty() {
+                            command="There is nothing to yank here".to_string();
+                        } else {
+                            let text=serialize(objects,&options.format);
+                            let text=text.trim_end().to_string();
+                            command=match objects.len() {
+                                1=>"Yanked 1 object".to_string(),
+                                n=>format!("Yanked {} objects",n),
+                            };
+                            if options.clipboard {
+                                if let Err(e)=clipboard::copy(term.backend_mut(),&text) {
+                                    command=format!("Could not copy to the clipboard. Reason: {}",e);
+                                }
+                            }
+                            yanked=Some(text);
+                        }
+                        changed=true;
+                    },
+                    KeyCode::Char('p') if mode.is_structural()=>{
+                        match yanked.as_deref().map(|text|Object::parse_snippet(text,options.dialect)) {
+                            Some(Ok(objects))=>{
+                                insert_objects(&mut contents,&mut cursor,mode.len(),objects);
+                                #[cfg(debug_assertions)]
+                                {mode=make_valid(&mut cursor,&contents,&mut debug_log)}
+                                #[cfg(not(debug_assertions))]
+                                {mode=make_valid(&mut cursor,&contents)}
+                                saved=false;
+                            },
+                            Some(Err(e))=>command=format!("Could not read the yanked text. Reason: {}",e),
+                            None=>command="Nothing was yanked yet".to_string(),
+                        }
+                        changed=true;
+                    },
+                    // Typing objects as text, which are read the way a file is
+                    KeyCode::Char('a') if mode.is_structural()=>{
+                        command=":insert ".to_string();
+                        command_cursor=command.len();
+                        mode.set_command();
+                        execute!(term.backend_mut(),ShowCursor).unwrap();
+                        changed=true;
+                    },
                     KeyCode::Char('i') if mode.is_structural()=>{
-                        let obj=Object::Ident(String::new());
+                        let obj=Object::Ident(String::new(),None);
                         if mode.len()>0&&*cursor.last().unwrap()<mode.len() {*cursor.last_mut().unwrap()+=1}
                         if cursor.len()==1 {
                             if cursor[0]>contents.len() {
@@ -520,7 +1337,7 @@ fn main() {
                         saved=false;
                     },
                     KeyCode::Char('n') if mode.is_structural()=>{
-                        let obj=Object::Number("0".into());
+                        let obj=Object::Number("0".into(),None);
                         if mode.len()>0&&*cursor.last().unwrap()<mode.len() {*cursor.last_mut().unwrap()+=1}
                         if cursor.len()==1 {
                             if cursor[0]>contents.len() {
@@ -543,8 +1360,157 @@ fn main() {
             _=>{},
         }
     }
+    swap_file.remove();
     disable_raw_mode().unwrap();
-    execute!(term.backend_mut(),LeaveAlternateScreen).unwrap();
+    execute!(term.backend_mut(),DisableMouseCapture,DisableBracketedPaste,LeaveAlternateScreen).unwrap();
+}
+/// Inserts objects after the object under the cursor, or at the cursor when it is at the end of a
+/// list, and moves the cursor to the last one. `len` is the length of the list the cursor is in.
+fn insert_objects(contents:&mut Vec<Object>,cursor:&mut [usize],len:usize,objects:Vec<Object>) {
+    if len>0&&*cursor.last().unwrap()<len {
+        *cursor.last_mut().unwrap()+=1;
+    }
+    for (i,object) in objects.into_iter().enumerate() {
+        if i>0 {
+            *cursor.last_mut().unwrap()+=1;
+        }
+        if cursor.len()==1 {
+            if cursor[0]>contents.len() {
+                contents.push(object);
+            } else {
+                contents.insert(cursor[0],object);
+            }
+        } else {
+            contents[cursor[0]].add_object(&cursor[1..],object);
+        }
+    }
+}
+/// The selected objects, or the object under the cursor if nothing is selected. Empty if the
+/// cursor is at the end of a list.
+fn selected<'a>(contents:&'a [Object],cursor:&[usize],selection:Option<usize>)->&'a [Object] {
+    let siblings=match object_at(contents,&cursor[..cursor.len()-1]) {
+        _ if cursor.len()==1=>contents,
+        Some(Object::List(items,..))=>items.as_slice(),
+        _=>&[],
+    };
+    let last=*cursor.last().unwrap();
+    let (first,last)=match selection {
+        Some(anchor)=>(anchor.min(last),anchor.max(last)),
+        None=>(last,last),
+    };
+    siblings.get(first..=last).unwrap_or(&[])
+}
+/// Replaces `count` objects starting at `first` in the list the cursor is in, and moves the cursor
+/// to the last new object.
+fn replace_objects(contents:&mut Vec<Object>,cursor:&mut [usize],first:usize,count:usize,objects:Vec<Object>) {
+    *cursor.last_mut().unwrap()=first;
+    for _ in 0..count {
+        if cursor.len()==1 {
+            contents.remove(first);
+        } else {
+            contents[cursor[0]].remove(&cursor[1..]);
+        }
+    }
+    for object in objects {
+        if cursor.len()==1 {
+            contents.insert(cursor[0],object);
+        } else {
+            contents[cursor[0]].add_object(&cursor[1..],object);
+        }
+        *cursor.last_mut().unwrap()+=1;
+    }
+    *cursor.last_mut().unwrap()=cursor.last().unwrap().saturating_sub(1);
+}
+/// Runs a shell command with `input` on its stdin. Returns what it printed to stdout and stderr, or
+/// what it printed to stderr if it failed. A command still running after `FILTER_TIMEOUT` is killed,
+/// so one that waits for the terminal can't freeze the editor.
+fn run_filter(command:&str,input:String)->Result<(String,String),String> {
+    let mut child=ProcessCommand::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e|format!("Could not run `{}`. Reason: {}",command,e))?;
+    // written and read from other threads, so a command that prints before it has read everything
+    // can't block on a full pipe
+    let mut stdin=child.stdin.take().unwrap();
+    let writer=spawn(move||stdin.write_all(input.as_bytes()));
+    let stdout=read_pipe(child.stdout.take().unwrap());
+    let stderr=read_pipe(child.stderr.take().unwrap());
+    let deadline=Instant::now()+FILTER_TIMEOUT;
+    let status=loop {
+        match child.try_wait() {
+            Ok(Some(status))=>break status,
+            Ok(None) if Instant::now()<deadline=>sleep(Duration::from_millis(10)),
+            Ok(None)=>{
+                let _=child.kill();
+                let _=child.wait();
+                return Err(format!("`{}` was stopped after running for {} seconds",command,FILTER_TIMEOUT.as_secs()));
+            },
+            Err(e)=>return Err(format!("Could not run `{}`. Reason: {}",command,e)),
+        }
+    };
+    // a command that doesn't read its input closes the pipe early, which is not a problem
+    let _=writer.join();
+    // whatever the command started in the background may still hold the pipes open, so they are
+    // only waited on until the deadline
+    let left=deadline.saturating_duration_since(Instant::now());
+    let stdout=stdout.recv_timeout(left).unwrap_or_default();
+    let stderr=stderr.recv_timeout(Duration::from_millis(100)).unwrap_or_default();
+    let stderr=String::from_utf8_lossy(&stderr)
+        .lines()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !status.success() {
+        return Err(format!("`{}` failed with {}. {}",command,status,stderr));
+    }
+    match String::from_utf8(stdout) {
+        Ok(stdout)=>Ok((stdout,stderr)),
+        Err(_)=>Err(format!("`{}` printed something that isn't UTF-8. {}",command,stderr)),
+    }
+}
+/// Reads everything from a pipe on another thread, and sends it once the pipe is closed.
+fn read_pipe(mut pipe:impl Read+Send+'static)->Receiver<Vec<u8>> {
+    let (sender,receiver)=channel();
+    spawn(move||{
+        let mut bytes=Vec::new();
+        let _=pipe.read_to_end(&mut bytes);
+        let _=sender.send(bytes);
+    });
+    receiver
+}
+/// Why the atom being edited can't be left as it is, if it is being edited and can't.
+fn editing_problem(contents:&[Object],cursor:&[usize],mode:&Mode)->Option<String> {
+    if !mode.is_edit() {
+        return None;
+    }
+    object_at(contents,&cursor[..cursor.len()-1])?.problem()
+}
+/// Asks on the plain terminal whether to recover a swap file that is newer than `filename`.
+/// Returns the recovered text if the user said yes. A declined swap file is deleted. A swap file
+/// that can't be parsed is an error for the message line, since the terminal is cleared once the
+/// editor starts.
+fn ask_recover(filename:&str,dialect:&Dialect)->Result<Option<String>,String> {
+    let swap_contents=match recoverable(filename) {
+        Some(swap_contents)=>swap_contents,
+        None=>return Ok(None),
+    };
+    if let Err(e)=Object::parse_file(&swap_contents,dialect) {
+        return Err(format!("Found swap file `{}`, but it could not be parsed: {}",swap_path(filename).display(),e));
+    }
+    print!("Found swap file `{}` that is newer than `{}`. Recover it? [y/N] ",swap_path(filename).display(),filename);
+    let mut answer=String::new();
+    if stdout().flush().is_err()||stdin().read_line(&mut answer).is_err() {
+        return Ok(None);
+    }
+    if answer.trim().eq_ignore_ascii_case("y") {
+        Ok(Some(swap_contents))
+    } else {
+        let _=remove_file(swap_path(filename));
+        Ok(None)
+    }
 }
 /// Returns Ok(count) for edit mode and Err(count) for just valid
 #[cfg(debug_assertions)]
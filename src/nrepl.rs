@@ -0,0 +1,449 @@
+//! A client for nREPL, the network REPL most Clojure tooling talks to. Messages are bencoded
+//! dictionaries sent over TCP, and every reply carries the id of the request it answers.
+use std::{
+    collections::BTreeMap,
+    net::TcpStream,
+    io::{
+        Read,
+        Write,
+        Result as IoResult,
+    },
+    sync::mpsc::{
+        channel,
+        Receiver,
+    },
+    thread,
+};
+
+
+/// A bencoded value. Byte strings are kept as text, since nREPL only sends UTF-8.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Bencode {
+    Int(i64),
+    Bytes(String),
+    List(Vec<Self>),
+    Dict(BTreeMap<String,Self>),
+}
+impl Bencode {
+    /// Builds a dictionary of strings, which is what every request is.
+    pub fn dict(pairs:&[(&str,&str)])->Self {
+        Bencode::Dict(pairs.iter()
+            .map(|(key,value)|(key.to_string(),Bencode::Bytes(value.to_string())))
+            .collect())
+    }
+    pub fn encode(&self,out:&mut Vec<u8>) {
+        match self {
+            Self::Int(i)=>out.extend(format!("i{}e",i).bytes()),
+            Self::Bytes(s)=>{
+                out.extend(format!("{}:",s.len()).bytes());
+                out.extend(s.bytes());
+            },
+            Self::List(items)=>{
+                out.push(b'l');
+                items.iter().for_each(|item|item.encode(out));
+                out.push(b'e');
+            },
+            Self::Dict(pairs)=>{
+                out.push(b'd');
+                for (key,value) in pairs {
+                    Self::Bytes(key.clone()).encode(out);
+                    value.encode(out);
+                }
+                out.push(b'e');
+            },
+        }
+    }
+    /// Decodes the value at the start of `input`. Returns the value and how many bytes it took, or
+    /// `None` if `input` ends before the value does.
+    pub fn decode(input:&[u8])->Result<Option<(Self,usize)>,String> {
+        Self::decode_at(input,0)
+    }
+    fn decode_at(input:&[u8],at:usize)->Result<Option<(Self,usize)>,String> {
+        match input.get(at) {
+            None=>Ok(None),
+            Some(b'i')=>{
+                let end=match find(input,at,b'e') {
+                    Some(end)=>end,
+                    None=>return Ok(None),
+                };
+                let text=String::from_utf8_lossy(&input[at+1..end]);
+                let int=text.parse().map_err(|_|format!("Invalid integer `{}`",text))?;
+                Ok(Some((Self::Int(int),end+1)))
+            },
+            Some(b'0'..=b'9')=>{
+                let colon=match find(input,at,b':') {
+                    Some(colon)=>colon,
+                    None=>return Ok(None),
+                };
+                let text=String::from_utf8_lossy(&input[at..colon]);
+                let len:usize=text.parse().map_err(|_|format!("Invalid length `{}`",text))?;
+                let end=colon+1+len;
+                if input.len()<end {
+                    return Ok(None);
+                }
+                Ok(Some((Self::Bytes(String::from_utf8_lossy(&input[colon+1..end]).into_owned()),end)))
+            },
+            Some(kind@(b'l'|b'd'))=>{
+                let mut at=at+1;
+                let mut items=Vec::new();
+                loop {
+                    match input.get(at) {
+                        None=>return Ok(None),
+                        Some(b'e')=>break,
+                        Some(_)=>match Self::decode_at(input,at)? {
+                            Some((item,next))=>{
+                                items.push(item);
+                                at=next;
+                            },
+                            None=>return Ok(None),
+                        },
+                    }
+                }
+                if *kind==b'l' {
+                    return Ok(Some((Self::List(items),at+1)));
+                }
+                let mut pairs=BTreeMap::new();
+                let mut items=items.into_iter();
+                while let Some(key)=items.next() {
+                    match (key,items.next()) {
+                        (Self::Bytes(key),Some(value))=>{
+                            pairs.insert(key,value);
+                        },
+                        _=>return Err("Invalid dictionary".to_string()),
+                    }
+                }
+                Ok(Some((Self::Dict(pairs),at+1)))
+            },
+            Some(byte)=>Err(format!("Unexpected byte `{}`",*byte as char)),
+        }
+    }
+    pub fn get(&self,key:&str)->Option<&Self> {
+        match self {
+            Self::Dict(pairs)=>pairs.get(key),
+            _=>None,
+        }
+    }
+    pub fn as_str(&self)->Option<&str> {
+        match self {
+            Self::Bytes(s)=>Some(s),
+            _=>None,
+        }
+    }
+    pub fn as_list(&self)->&[Self] {
+        match self {
+            Self::List(items)=>items,
+            _=>&[],
+        }
+    }
+}
+fn find(input:&[u8],from:usize,byte:u8)->Option<usize> {
+    input[from..].iter().position(|b|*b==byte).map(|i|i+from)
+}
+
+
+/// Something the server said, in the order it said it.
+#[derive(Debug,Clone,PartialEq)]
+pub enum Reply {
+    /// The printed value of an evaluated form.
+    Value(String),
+    Out(String),
+    Err(String),
+    /// The class of an exception thrown by an evaluation. The trace comes on `Err`.
+    Exception(String),
+    Completions(Vec<String>),
+    /// A status worth showing, like `interrupted` or `unknown-op`.
+    Status(String),
+    /// The connection closed or sent something that isn't bencode.
+    Closed(String),
+}
+
+
+pub struct Nrepl {
+    stream:TcpStream,
+    receiver:Receiver<Result<Bencode,String>>,
+    session:Option<String>,
+    next_id:usize,
+    /// The id of the last evaluation, which is what `interrupt` stops.
+    last_eval:Option<String>,
+    /// Requests waiting for the session to be created.
+    queued:Vec<BTreeMap<String,Bencode>>,
+    connected:bool,
+}
+impl Nrepl {
+    /// Connects to a server on localhost and asks it for a session.
+    pub fn connect(port:u16)->IoResult<Self> {
+        let stream=TcpStream::connect(("127.0.0.1",port))?;
+        let reader=stream.try_clone()?;
+        let (sender,receiver)=channel();
+        thread::spawn(move||read_messages(reader,|message|sender.send(message).is_ok()));
+        let mut nrepl=Nrepl {
+            stream,
+            receiver,
+            session:None,
+            next_id:0,
+            last_eval:None,
+            queued:Vec::new(),
+            connected:true,
+        };
+        nrepl.write(&[("op","clone")])?;
+        Ok(nrepl)
+    }
+    pub fn is_connected(&self)->bool {
+        self.connected
+    }
+    pub fn eval(&mut self,code:&str)->IoResult<()> {
+        let id=self.request(&[("op","eval"),("code",code)])?;
+        self.last_eval=Some(id);
+        Ok(())
+    }
+    /// Loads a whole file, so its namespace and definitions are set up like `require` would.
+    pub fn load_file(&mut self,contents:&str,path:&str)->IoResult<()> {
+        let name=path.rsplit('/').next().unwrap_or(path);
+        let id=self.request(&[("op","load-file"),("file",contents),("file-path",path),("file-name",name)])?;
+        self.last_eval=Some(id);
+        Ok(())
+    }
+    /// Stops the last evaluation if it is still running.
+    pub fn interrupt(&mut self)->IoResult<()> {
+        match self.last_eval.clone() {
+            Some(id)=>self.request(&[("op","interrupt"),("interrupt-id",&id)]).map(|_|()),
+            None=>Ok(()),
+        }
+    }
+    pub fn completions(&mut self,prefix:&str)->IoResult<()> {
+        self.request(&[("op","completions"),("prefix",prefix)]).map(|_|())
+    }
+    /// Everything the server said since the last call.
+    pub fn poll(&mut self)->IoResult<Vec<Reply>> {
+        let mut replies=Vec::new();
+        while let Ok(message)=self.receiver.try_recv() {
+            let message=match message {
+                Ok(message)=>message,
+                Err(reason)=>{
+                    self.connected=false;
+                    replies.push(Reply::Closed(reason));
+                    continue;
+                },
+            };
+            if let Some(session)=message.get("new-session").and_then(Bencode::as_str) {
+                if self.session.is_none() {
+                    self.session=Some(session.to_string());
+                    for request in std::mem::take(&mut self.queued) {
+                        self.send(request)?;
+                    }
+                }
+            }
+            if let Some(out)=message.get("out").and_then(Bencode::as_str) {
+                replies.push(Reply::Out(out.to_string()));
+            }
+            if let Some(err)=message.get("err").and_then(Bencode::as_str) {
+                replies.push(Reply::Err(err.to_string()));
+            }
+            if let Some(value)=message.get("value").and_then(Bencode::as_str) {
+                replies.push(Reply::Value(value.to_string()));
+            }
+            if let Some(ex)=message.get("ex").and_then(Bencode::as_str) {
+                replies.push(Reply::Exception(ex.to_string()));
+            }
+            if let Some(completions)=message.get("completions") {
+                replies.push(Reply::Completions(completions.as_list().iter()
+                    .filter_map(|completion|completion.get("candidate").and_then(Bencode::as_str))
+                    .map(str::to_string)
+                    .collect()));
+            }
+            for status in message.get("status").map(Bencode::as_list).unwrap_or_default() {
+                if let Some(status@("interrupted"|"unknown-op"|"error"|"namespace-not-found"|"session-idle"))=status.as_str() {
+                    replies.push(Reply::Status(status.to_string()));
+                }
+            }
+        }
+        Ok(replies)
+    }
+    /// Sends a request in the session, queueing it until the session exists. Returns its id.
+    fn request(&mut self,pairs:&[(&str,&str)])->IoResult<String> {
+        let id=self.next_id.to_string();
+        self.next_id+=1;
+        let mut request=match Bencode::dict(pairs) {
+            Bencode::Dict(request)=>request,
+            _=>unreachable!(),
+        };
+        request.insert("id".to_string(),Bencode::Bytes(id.clone()));
+        if self.session.is_some() {
+            self.send(request)?;
+        } else {
+            self.queued.push(request);
+        }
+        Ok(id)
+    }
+    fn send(&mut self,mut request:BTreeMap<String,Bencode>)->IoResult<()> {
+        if let Some(session)=&self.session {
+            request.insert("session".to_string(),Bencode::Bytes(session.clone()));
+        }
+        let mut out=Vec::new();
+        Bencode::Dict(request).encode(&mut out);
+        self.stream.write_all(&out)
+    }
+    /// Sends a request outside of any session.
+    fn write(&mut self,pairs:&[(&str,&str)])->IoResult<()> {
+        let mut out=Vec::new();
+        Bencode::dict(pairs).encode(&mut out);
+        self.stream.write_all(&out)
+    }
+}
+impl Drop for Nrepl {
+    fn drop(&mut self) {
+        if let Some(session)=self.session.clone() {
+            let _=self.write(&[("op","close"),("session",&session)]);
+        }
+    }
+}
+
+
+/// Reads bencoded messages from `stream` until it closes, handing each to `handle`. Stops early
+/// when `handle` returns false.
+fn read_messages(mut stream:impl Read,handle:impl Fn(Result<Bencode,String>)->bool) {
+    let mut buf=Vec::new();
+    let mut chunk=[0;4096];
+    loop {
+        // hand out every complete message in the buffer
+        loop {
+            match Bencode::decode(&buf) {
+                Ok(Some((message,len)))=>{
+                    buf.drain(..len);
+                    if !handle(Ok(message)) {
+                        return;
+                    }
+                },
+                Ok(None)=>break,
+                Err(e)=>{
+                    handle(Err(e));
+                    return;
+                },
+            }
+        }
+        match stream.read(&mut chunk) {
+            Ok(0)|Err(_)=>{
+                handle(Err("The connection closed".to_string()));
+                return;
+            },
+            Ok(read)=>buf.extend_from_slice(&chunk[..read]),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        net::{
+            TcpListener,
+            Shutdown,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
+    };
+
+    fn round_trip(value:&Bencode)->Bencode {
+        let mut out=Vec::new();
+        value.encode(&mut out);
+        let (decoded,len)=Bencode::decode(&out).unwrap().unwrap();
+        assert_eq!(len,out.len());
+        decoded
+    }
+    fn message(pairs:Vec<(&str,Bencode)>)->Vec<u8> {
+        let mut out=Vec::new();
+        Bencode::Dict(pairs.into_iter().map(|(key,value)|(key.to_string(),value)).collect()).encode(&mut out);
+        out
+    }
+    fn bytes(s:&str)->Bencode {
+        Bencode::Bytes(s.to_string())
+    }
+
+    #[test]
+    fn encodes() {
+        let mut out=Vec::new();
+        Bencode::List(vec![Bencode::Int(-3),bytes("spam"),Bencode::dict(&[("b","2"),("a","1")])]).encode(&mut out);
+        assert_eq!(out,b"li-3e4:spamd1:a1:11:b1:2ee");
+    }
+    #[test]
+    fn round_trips() {
+        let nested=Bencode::Dict([
+            ("list".to_string(),Bencode::List(vec![Bencode::Int(0),Bencode::List(Vec::new()),bytes("")])),
+            ("dict".to_string(),Bencode::Dict([("inner".to_string(),Bencode::Int(42))].into_iter().collect())),
+            // lengths count bytes, not chars
+            ("text".to_string(),bytes("λ → 😀")),
+        ].into_iter().collect());
+        assert_eq!(round_trip(&nested),nested);
+        assert_eq!(round_trip(&bytes("é")),bytes("é"));
+    }
+    #[test]
+    fn decodes_partial_input() {
+        let mut out=Vec::new();
+        Bencode::dict(&[("value","λ")]).encode(&mut out);
+        for end in 0..out.len() {
+            assert_eq!(Bencode::decode(&out[..end]),Ok(None));
+        }
+        assert!(Bencode::decode(b"x").is_err());
+        assert!(Bencode::decode(b"ie").is_err());
+    }
+    #[test]
+    fn talks_to_a_server() {
+        let listener=TcpListener::bind(("127.0.0.1",0)).unwrap();
+        let port=listener.local_addr().unwrap().port();
+        let (sender,requests)=channel();
+        let server=thread::spawn(move||{
+            let (mut stream,_)=listener.accept().unwrap();
+            let reader=stream.try_clone().unwrap();
+            let (message_sender,messages)=channel();
+            thread::spawn(move||read_messages(reader,|message|message_sender.send(message).is_ok()));
+            let clone=messages.recv().unwrap().unwrap();
+            sender.send(clone.clone()).unwrap();
+            stream.write_all(&message(vec![
+                ("new-session",bytes("s1")),
+                ("status",Bencode::List(vec![bytes("done")])),
+            ])).unwrap();
+            let eval=messages.recv().unwrap().unwrap();
+            sender.send(eval.clone()).unwrap();
+            let id=eval.get("id").unwrap().clone();
+            let mut replies=Vec::new();
+            for (key,value) in [("out","hi\n"),("err","careful\n"),("value","3")] {
+                replies.extend(message(vec![("id",id.clone()),("session",bytes("s1")),(key,bytes(value))]));
+            }
+            replies.extend(message(vec![("id",id),("session",bytes("s1")),("status",Bencode::List(vec![bytes("done")]))]));
+            // split in the middle of a message, so the client has to wait for the rest
+            let (first,rest)=replies.split_at(replies.len()/2+3);
+            stream.write_all(first).unwrap();
+            stream.flush().unwrap();
+            thread::sleep(Duration::from_millis(50));
+            stream.write_all(rest).unwrap();
+            stream.shutdown(Shutdown::Both).unwrap();
+        });
+        let mut nrepl=Nrepl::connect(port).unwrap();
+        // sent once the session exists
+        nrepl.eval("(+ 1 2)").unwrap();
+        let mut replies=Vec::new();
+        let start=Instant::now();
+        while nrepl.is_connected()&&start.elapsed()<Duration::from_secs(5) {
+            replies.extend(nrepl.poll().unwrap());
+            thread::sleep(Duration::from_millis(10));
+        }
+        server.join().unwrap();
+        let clone=requests.recv().unwrap();
+        assert_eq!(clone.get("op").and_then(Bencode::as_str),Some("clone"));
+        let eval=requests.recv().unwrap();
+        assert_eq!(eval.get("op").and_then(Bencode::as_str),Some("eval"));
+        assert_eq!(eval.get("code").and_then(Bencode::as_str),Some("(+ 1 2)"));
+        assert_eq!(eval.get("session").and_then(Bencode::as_str),Some("s1"));
+        // `done` is routine, so it isn't reported, and the server hung up after it
+        assert_eq!(replies,vec![
+            Reply::Out("hi\n".to_string()),
+            Reply::Err("careful\n".to_string()),
+            Reply::Value("3".to_string()),
+            Reply::Closed("The connection closed".to_string()),
+        ]);
+        assert!(!nrepl.is_connected());
+    }
+}
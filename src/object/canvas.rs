@@ -0,0 +1,75 @@
+use tui::{
+    style::Style,
+    layout::Rect,
+    buffer::Buffer,
+};
+use super::*;
+
+
+/// A view of a `Buffer` that laid out objects are drawn onto. Positions are in the layout, and
+/// are moved by the lines scrolled off the top. Anything outside of the area is clipped instead of
+/// panicking.
+pub struct Canvas<'buf> {
+    buf:&'buf mut Buffer,
+    area:Rect,
+    scroll:usize,
+}
+impl<'buf> Canvas<'buf> {
+    pub fn new(buf:&'buf mut Buffer,area:Rect,scroll:usize)->Self {
+        Canvas{buf,area,scroll}
+    }
+    /// Converts a layout position to a screen position if it is visible.
+    fn screen(&self,position:Position)->Option<(u16,u16)> {
+        let Position{line,column}=position;
+        if line>=self.scroll&&line-self.scroll<self.area.height as usize&&column<self.area.width as usize {
+            Some((self.area.x+column as u16,self.area.y+(line-self.scroll) as u16))
+        } else {
+            None
+        }
+    }
+    /// Writes `string` at `position`, clipping whatever does not fit.
+    pub fn set_string(&mut self,position:Position,string:&str,style:Style) {
+        if let Some((x,y))=self.screen(position) {
+            let width=(self.area.right()-x) as usize;
+            self.buf.set_stringn(x,y,string,width,style);
+        }
+    }
+    /// Changes the style of a single cell if it is visible.
+    pub fn set_style(&mut self,position:Position,style:Style) {
+        if let Some((x,y))=self.screen(position) {
+            self.buf.get_mut(x,y).set_style(style);
+        }
+    }
+    /// Patches the style of everything a node covers. Indentation on the lines after the first is
+    /// left alone, so the highlight follows the shape of the text.
+    pub fn highlight(&mut self,node:&NodeSpan,style:Style) {
+        let width=self.area.width as usize;
+        for line in node.start.line..=node.end.line {
+            let first=if line==node.start.line {
+                node.start.column
+            } else {
+                (0..width)
+                    .find(|column|!self.is_blank(Position{line,column:*column}))
+                    .unwrap_or(width)
+            };
+            let last=if line==node.end.line {
+                node.end.column
+            } else {
+                (0..width)
+                    .rev()
+                    .find(|column|!self.is_blank(Position{line,column:*column}))
+                    .map(|column|column+1)
+                    .unwrap_or(0)
+            };
+            for column in first..last {
+                self.set_style(Position{line,column},style);
+            }
+        }
+    }
+    fn is_blank(&self,position:Position)->bool {
+        match self.screen(position) {
+            Some((x,y))=>self.buf.get(x,y).symbol==" ",
+            None=>true,
+        }
+    }
+}
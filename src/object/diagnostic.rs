@@ -0,0 +1,38 @@
+//! Problems found in the objects, attached to the node they are about.
+use tui::style::Color;
+use super::*;
+
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+impl Severity {
+    pub fn color(&self,colors:&Colors)->Color {
+        match self {
+            Self::Error=>colors.error,
+            Self::Warning=>colors.warning,
+            Self::Info=>colors.info,
+        }
+    }
+}
+
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Diagnostic {
+    /// The path of the node the problem is about.
+    pub path:Vec<usize>,
+    pub severity:Severity,
+    pub message:String,
+}
+impl Diagnostic {
+    /// The diagnostic about the innermost node containing `cursor`, the worst one if there are
+    /// several.
+    pub fn at<'a>(diagnostics:&'a [Self],cursor:&[usize])->Option<&'a Self> {
+        diagnostics.iter()
+            .filter(|diagnostic|cursor.starts_with(&diagnostic.path))
+            .max_by(|a,b|a.path.len().cmp(&b.path.len()).then(b.severity.cmp(&a.severity)))
+    }
+}
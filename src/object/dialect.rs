@@ -0,0 +1,411 @@
+//! Dialect profiles: what the reader accepts for each Lisp, and the indent rules and highlighting
+//! that go with it.
+use std::path::Path;
+use super::*;
+
+
+/// The brackets a list is written with.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum Delimiter {
+    #[default]
+    Paren,
+    Bracket,
+    Brace,
+}
+impl Delimiter {
+    pub fn open(&self)->&'static str {
+        match self {
+            Self::Paren=>"(",
+            Self::Bracket=>"[",
+            Self::Brace=>"{",
+        }
+    }
+    pub fn close(&self)->&'static str {
+        match self {
+            Self::Paren=>")",
+            Self::Bracket=>"]",
+            Self::Brace=>"}",
+        }
+    }
+    fn from_open(c:char)->Option<Self> {
+        match c {
+            '('=>Some(Self::Paren),
+            '['=>Some(Self::Bracket),
+            '{'=>Some(Self::Brace),
+            _=>None,
+        }
+    }
+    fn from_close(c:char)->Option<Self> {
+        match c {
+            ')'=>Some(Self::Paren),
+            ']'=>Some(Self::Bracket),
+            '}'=>Some(Self::Brace),
+            _=>None,
+        }
+    }
+    /// The delimiter a list starting with `c` uses. Anything unknown is a paren.
+    pub fn of(c:Option<char>)->Self {
+        c.and_then(Self::from_open).unwrap_or_default()
+    }
+}
+
+
+/// How a symbol is highlighted beyond being a symbol.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Highlight {
+    /// The head of a special form or built in macro, like `define` or `if`.
+    SpecialForm,
+    /// The name given to something by a definition, like `f` in `(define (f x) ...)`.
+    Definition,
+    /// Booleans and nil, like `#t` or `nil`.
+    Constant,
+    /// `:keyword`
+    Keyword,
+    /// A character, like `#\a` or `\a`.
+    Char,
+}
+
+
+/// The reader and style of one Lisp dialect.
+#[derive(Debug)]
+pub struct Dialect {
+    pub name:&'static str,
+    /// File extensions that pick this dialect, without the dot.
+    pub extensions:&'static [&'static str],
+    /// The name of the indent rules for `IndentRules::named`.
+    pub indent:&'static str,
+    /// `;` starts a comment that runs to the end of the line.
+    pub line_comments:bool,
+    /// `#|` and `|#` around comments, which nest.
+    pub block_comments:bool,
+    /// Commas are whitespace.
+    pub comma_whitespace:bool,
+    /// The delimiters lists can use besides parens.
+    pub delimiters:&'static [Delimiter],
+    /// What starts a character literal. The char after it is never read as a delimiter.
+    pub char_prefix:Option<&'static str>,
+    /// Symbols that are constants, like booleans and nil.
+    pub constants:&'static [&'static str],
+    /// Symbols starting with `:` are keywords.
+    pub keywords:bool,
+    /// Heads of special forms and macros that are highlighted. The ones starting with `def` also
+    /// highlight the name they define.
+    pub special_forms:&'static [&'static str],
+    /// How many arguments special forms take: at least and at most, if there is a limit.
+    pub arities:&'static [(&'static str,usize,Option<usize>)],
+    /// Save with the layout-preserving mode, so comments and the existing layout survive.
+    pub preserve:bool,
+    /// The `languageId` documents are opened with in a language server.
+    pub language_id:&'static str,
+    /// The language server `:lsp` starts when no command is given.
+    pub language_server:Option<&'static str>,
+}
+impl Dialect {
+    /// The dialect used when nothing else fits: parens only and `;` comments.
+    pub fn generic()->&'static Self {
+        &DIALECTS[0]
+    }
+    pub fn named(name:&str)->Option<&'static Self> {
+        DIALECTS.iter().find(|dialect|dialect.name==name)
+    }
+    /// The dialect for a file, picked by its extension.
+    pub fn for_file(filename:&str)->&'static Self {
+        let extension=Path::new(filename)
+            .extension()
+            .and_then(|extension|extension.to_str())
+            .unwrap_or("");
+        DIALECTS.iter()
+            .find(|dialect|dialect.extensions.contains(&extension))
+            .unwrap_or_else(Self::generic)
+    }
+    /// The names accepted by `Dialect::named`.
+    pub fn names()->Vec<&'static str> {
+        DIALECTS.iter().map(|dialect|dialect.name).collect()
+    }
+    /// Checks if a list can be written with `delimiter`.
+    pub fn allows(&self,delimiter:Delimiter)->bool {
+        delimiter==Delimiter::Paren||self.delimiters.contains(&delimiter)
+    }
+    /// How the symbol at `path` is highlighted, if it is anything special.
+    pub fn highlight(&self,objects:&[Object],path:&[usize])->Option<Highlight> {
+        let symbol=match object_at(objects,path)? {
+            Object::Ident(symbol,_)=>symbol,
+            _=>return None,
+        };
+        if self.constants.contains(&symbol.as_str()) {
+            return Some(Highlight::Constant);
+        }
+        if self.keywords&&symbol.len()>1&&symbol.starts_with(':') {
+            return Some(Highlight::Keyword);
+        }
+        if let Some(prefix)=self.char_prefix {
+            if symbol.len()>prefix.len()&&symbol.starts_with(prefix) {
+                return Some(Highlight::Char);
+            }
+        }
+        let (last,parent)=path.split_last()?;
+        if *last==0&&!parent.is_empty()&&self.special_forms.contains(&symbol.as_str()) {
+            return Some(Highlight::SpecialForm);
+        }
+        // `(def name ...)`, or `(define (name args) ...)`
+        let definition=match (last,parent.split_last()) {
+            (1,_)=>parent,
+            (0,Some((1,grandparent)))=>grandparent,
+            _=>return None,
+        };
+        match object_at(objects,definition) {
+            Some(Object::List(items,..))=>match items.first() {
+                Some(Object::Ident(head,_)) if head.starts_with("def")&&self.special_forms.contains(&head.as_str())=>Some(Highlight::Definition),
+                _=>None,
+            },
+            _=>None,
+        }
+    }
+    /// Checks if a character literal starts at byte `i`. It has to be the start of a symbol, so
+    /// `?` in `null?` doesn't count.
+    fn is_char_literal(&self,text:&str,i:usize)->bool {
+        let prefix=match self.char_prefix {
+            Some(prefix)=>prefix,
+            None=>return false,
+        };
+        let before=text[..i].chars().next_back();
+        text[i..].starts_with(prefix)&&!matches!(before,Some(c) if !c.is_whitespace()&&!"()[]{}'`,@#".contains(c))
+    }
+    /// Rewrites the parts of `text` that the s-expression parser doesn't know about into things it
+    /// does, without moving anything: comments and whitespace commas become spaces, other
+    /// delimiters become parens, and delimiters in character literals become letters. Returns the
+    /// new text and whether there were any comments.
+    pub fn mask(&self,text:&str)->(String,bool) {
+        #[derive(PartialEq)]
+        enum State {
+            Code,
+            String,
+            Escape,
+            LineComment,
+            BlockComment(usize),
+        }
+        let mut out=String::with_capacity(text.len());
+        let mut comments=false;
+        let mut state=State::Code;
+        let mut chars=text.char_indices().peekable();
+        while let Some((i,c))=chars.next() {
+            let next=chars.peek().map(|(_,c)|*c);
+            match state {
+                State::Code=>match c {
+                    '"'=>{
+                        state=State::String;
+                        out.push(c);
+                    },
+                    ';' if self.line_comments=>{
+                        state=State::LineComment;
+                        comments=true;
+                        out.push(' ');
+                    },
+                    '#' if self.block_comments&&next==Some('|')=>{
+                        chars.next();
+                        state=State::BlockComment(1);
+                        comments=true;
+                        out.push_str("  ");
+                    },
+                    ',' if self.comma_whitespace=>out.push(' '),
+                    _ if self.is_char_literal(text,i)=>{
+                        let prefix=self.char_prefix.unwrap();
+                        out.push_str(prefix);
+                        for _ in 1..prefix.chars().count() {
+                            chars.next();
+                        }
+                        // the char itself is part of the symbol, whatever it is. Escapes like
+                        // `?\(` take one more char.
+                        let mut escaped=false;
+                        for (_,c) in chars.by_ref() {
+                            if c.is_alphanumeric() {
+                                out.push(c);
+                            } else {
+                                (0..c.len_utf8()).for_each(|_|out.push('x'));
+                            }
+                            if c=='\\'&&!escaped&&!prefix.ends_with('\\') {
+                                escaped=true;
+                            } else {
+                                break;
+                            }
+                        }
+                    },
+                    _=>match (Delimiter::from_open(c),Delimiter::from_close(c)) {
+                        (Some(delimiter),_) if self.allows(delimiter)=>out.push('('),
+                        (_,Some(delimiter)) if self.allows(delimiter)=>out.push(')'),
+                        _=>out.push(c),
+                    },
+                },
+                State::String=>{
+                    match c {
+                        '\\'=>state=State::Escape,
+                        '"'=>state=State::Code,
+                        _=>{},
+                    }
+                    out.push(c);
+                },
+                State::Escape=>{
+                    state=State::String;
+                    out.push(c);
+                },
+                State::LineComment=>if c=='\n' {
+                    state=State::Code;
+                    out.push(c);
+                } else {
+                    blank(&mut out,c);
+                },
+                State::BlockComment(depth)=>match (c,next) {
+                    ('|',Some('#'))|('#',Some('|'))=>{
+                        chars.next();
+                        out.push_str("  ");
+                        let depth=if c=='|' {depth-1} else {depth+1};
+                        state=if depth==0 {State::Code} else {State::BlockComment(depth)};
+                    },
+                    _=>blank(&mut out,c),
+                },
+            }
+        }
+        (out,comments)
+    }
+}
+/// Replaces a char with spaces, keeping newlines so positions stay on the same line.
+fn blank(out:&mut String,c:char) {
+    if c=='\n' {
+        out.push(c);
+    } else {
+        (0..c.len_utf8()).for_each(|_|out.push(' '));
+    }
+}
+/// Checks if a symbol is a reader prefix like `'` or `#`, which is written right before the next
+/// object instead of being separated from it.
+pub fn is_reader_prefix(symbol:&str)->bool {
+    matches!(symbol,"#_"|"#?"|"#?@")||
+        (!symbol.is_empty()&&symbol.chars().all(|c|"'`,@#^~".contains(c)))
+}
+
+
+pub static DIALECTS:&[Dialect]=&[
+    Dialect {
+        name:"generic",
+        extensions:&["sexp","sexpr"],
+        indent:"default",
+        line_comments:true,
+        block_comments:false,
+        comma_whitespace:false,
+        delimiters:&[],
+        char_prefix:None,
+        constants:&[],
+        keywords:false,
+        special_forms:&[],
+        arities:&[],
+        preserve:true,
+        language_id:"lisp",
+        language_server:None,
+    },
+    Dialect {
+        name:"scheme",
+        extensions:&["scm","ss","sld","sls","rkt"],
+        indent:"scheme",
+        line_comments:true,
+        block_comments:true,
+        comma_whitespace:false,
+        delimiters:&[Delimiter::Bracket],
+        char_prefix:Some("#\\"),
+        constants:&["#t","#f","#true","#false"],
+        keywords:false,
+        special_forms:&[
+            "define","define-syntax","define-record-type","define-values","lambda","case-lambda",
+            "let","let*","letrec","letrec*","let-values","let*-values","let-syntax","letrec-syntax",
+            "syntax-rules","if","cond","case","and","or","when","unless","do","begin","set!",
+            "quote","quasiquote","unquote","unquote-splicing","delay","parameterize","else","=>",
+        ],
+        arities:&[
+            ("if",2,Some(3)),("quote",1,Some(1)),("define",1,None),("lambda",2,None),("let",2,None),
+            ("let*",2,None),("letrec",2,None),("letrec*",2,None),("set!",2,Some(2)),("when",1,None),
+            ("unless",1,None),("delay",1,Some(1)),
+        ],
+        preserve:true,
+        language_id:"scheme",
+        language_server:Some("racket -l racket-langserver"),
+    },
+    Dialect {
+        name:"clojure",
+        extensions:&["clj","cljs","cljc","edn"],
+        indent:"clojure",
+        line_comments:true,
+        block_comments:false,
+        comma_whitespace:true,
+        delimiters:&[Delimiter::Bracket,Delimiter::Brace],
+        char_prefix:Some("\\"),
+        constants:&["true","false","nil"],
+        keywords:true,
+        special_forms:&[
+            "ns","def","defn","defn-","defmacro","defmulti","defmethod","defprotocol","defrecord",
+            "deftype","fn","let","loop","recur","if","if-let","if-not","when","when-let","when-not",
+            "cond","condp","case","do","try","catch","finally","throw","binding","doseq","dotimes",
+            "for","quote","var","new","set!","and","or",
+        ],
+        arities:&[
+            ("if",2,Some(3)),("quote",1,Some(1)),("var",1,Some(1)),("def",1,Some(3)),("defn",2,None),
+            ("defmacro",2,None),("fn",1,None),("let",1,None),("loop",1,None),("when",1,None),
+            ("if-let",2,Some(3)),("when-let",1,None),("throw",1,Some(1)),
+        ],
+        preserve:true,
+        language_id:"clojure",
+        language_server:Some("clojure-lsp"),
+    },
+    Dialect {
+        name:"lisp",
+        extensions:&["lisp","lsp","cl","asd"],
+        indent:"lisp",
+        line_comments:true,
+        block_comments:true,
+        comma_whitespace:false,
+        delimiters:&[],
+        char_prefix:Some("#\\"),
+        constants:&["t","nil"],
+        keywords:true,
+        special_forms:&[
+            "defun","defmacro","defvar","defparameter","defconstant","defgeneric","defmethod",
+            "defclass","defstruct","defpackage","in-package","lambda","let","let*","flet","labels",
+            "macrolet","if","cond","case","when","unless","and","or","progn","prog1","block",
+            "return","return-from","loop","do","dolist","dotimes","setf","setq","quote","function",
+            "multiple-value-bind","destructuring-bind","handler-case","unwind-protect","declare",
+        ],
+        arities:&[
+            ("if",2,Some(3)),("quote",1,Some(1)),("function",1,Some(1)),("defun",2,None),
+            ("defmacro",2,None),("defvar",1,Some(3)),("defparameter",2,Some(3)),
+            ("defconstant",2,Some(3)),("lambda",1,None),("let",1,None),("let*",1,None),
+            ("when",1,None),("unless",1,None),
+        ],
+        preserve:true,
+        language_id:"commonlisp",
+        language_server:None,
+    },
+    Dialect {
+        name:"elisp",
+        extensions:&["el"],
+        indent:"elisp",
+        line_comments:true,
+        block_comments:false,
+        comma_whitespace:false,
+        delimiters:&[Delimiter::Bracket],
+        char_prefix:Some("?"),
+        constants:&["t","nil"],
+        keywords:true,
+        special_forms:&[
+            "defun","defmacro","defvar","defcustom","defconst","defsubst","defgroup","lambda",
+            "let","let*","if","cond","when","unless","and","or","progn","prog1","while","dolist",
+            "dotimes","setq","quote","function","save-excursion","unwind-protect",
+            "condition-case","with-current-buffer","interactive",
+        ],
+        arities:&[
+            ("if",2,None),("quote",1,Some(1)),("function",1,Some(1)),("defun",2,None),
+            ("defmacro",2,None),("defvar",1,Some(3)),("defconst",2,Some(3)),("lambda",1,None),
+            ("let",1,None),("let*",1,None),("when",1,None),("unless",1,None),
+        ],
+        preserve:true,
+        language_id:"emacs-lisp",
+        language_server:None,
+    },
+];
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+
+/// How the items of a broken list are indented, picked by the symbol at its head.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Indent {
+    /// The first `n` arguments are special and stay on the line of the head if they fit,
+    /// otherwise they get twice the body indent. Everything after them is body and gets the body
+    /// indent, like `(define (f x)` followed by its body. `Body(0)` puts every argument on its own
+    /// line.
+    Body(usize),
+    /// Arguments line up under the first argument, like function calls.
+    Align,
+    /// Like `Body(2)`, but a docstring after the name is special as well, so the name, docstring
+    /// and parameters of a Clojure `defn` share the line of the head.
+    Defn,
+}
+impl Indent {
+    /// Parses a rule as written in `:indent`: `align`, `defn` or the amount of special arguments.
+    pub fn parse(rule:&str)->Option<Self> {
+        match rule {
+            "align"=>Some(Indent::Align),
+            "defn"=>Some(Indent::Defn),
+            n=>n.parse().ok().map(Indent::Body),
+        }
+    }
+}
+
+
+/// A set of indentation rules, usually the style of one Lisp dialect.
+#[derive(Debug,Clone)]
+pub struct IndentRules {
+    pub name:&'static str,
+    /// How far body forms are indented from the open paren.
+    pub body:usize,
+    /// The rule for heads that have no rule of their own.
+    pub default:Indent,
+    /// Heads starting with `def` get `Body(1)` unless they have a rule of their own.
+    pub def_forms:bool,
+    /// Line up the items of lists that don't start with a symbol, like `let` bindings, under the
+    /// first item. Otherwise they are treated like a list with a head that has the default rule.
+    pub align_data:bool,
+    pub forms:HashMap<String,Indent>,
+}
+impl Default for IndentRules {
+    /// Every item after the head on its own line, four spaces in.
+    fn default()->Self {
+        IndentRules {
+            name:"default",
+            body:4,
+            default:Indent::Body(0),
+            def_forms:false,
+            align_data:false,
+            forms:HashMap::new(),
+        }
+    }
+}
+impl IndentRules {
+    /// The names accepted by `IndentRules::named`.
+    pub const NAMES:&'static [&'static str]=&["default","lisp","elisp","scheme","clojure"];
+    /// The built in rules with the given name. The dialect rules follow what Emacs does by
+    /// default for each language.
+    pub fn named(name:&str)->Option<Self> {
+        use Indent::*;
+        let (name,forms):(&'static str,&[(&str,Indent)])=match name {
+            "default"=>return Some(Self::default()),
+            "lisp"=>("lisp",&[
+                ("defun",Body(2)),
+                ("defmacro",Body(2)),
+                ("defmethod",Body(2)),
+                ("defgeneric",Body(2)),
+                ("defclass",Body(2)),
+                ("lambda",Body(1)),
+                ("let",Body(1)),
+                ("let*",Body(1)),
+                ("flet",Body(1)),
+                ("labels",Body(1)),
+                ("macrolet",Body(1)),
+                ("when",Body(1)),
+                ("unless",Body(1)),
+                ("dolist",Body(1)),
+                ("dotimes",Body(1)),
+                ("block",Body(1)),
+                ("handler-case",Body(1)),
+                ("unwind-protect",Body(1)),
+                ("multiple-value-bind",Body(2)),
+                ("destructuring-bind",Body(2)),
+                ("progn",Body(0)),
+                ("if",Align),
+            ]),
+            "elisp"=>("elisp",&[
+                ("defun",Body(2)),
+                ("defmacro",Body(2)),
+                ("defsubst",Body(2)),
+                ("lambda",Body(1)),
+                ("let",Body(1)),
+                ("let*",Body(1)),
+                ("when",Body(1)),
+                ("unless",Body(1)),
+                ("while",Body(1)),
+                ("dolist",Body(1)),
+                ("dotimes",Body(1)),
+                ("with-current-buffer",Body(1)),
+                ("save-excursion",Body(0)),
+                ("progn",Body(0)),
+                ("unwind-protect",Body(1)),
+                ("condition-case",Body(2)),
+                ("if",Body(2)),
+            ]),
+            "scheme"=>("scheme",&[
+                ("define",Body(1)),
+                ("define-syntax",Body(1)),
+                ("define-record-type",Body(1)),
+                ("lambda",Body(1)),
+                ("let",Body(1)),
+                ("let*",Body(1)),
+                ("letrec",Body(1)),
+                ("letrec*",Body(1)),
+                ("let-values",Body(1)),
+                ("let*-values",Body(1)),
+                ("let-syntax",Body(1)),
+                ("syntax-rules",Body(1)),
+                ("when",Body(1)),
+                ("unless",Body(1)),
+                ("do",Body(2)),
+                ("case",Body(1)),
+                ("parameterize",Body(1)),
+                ("begin",Body(0)),
+                ("if",Align),
+            ]),
+            "clojure"=>("clojure",&[
+                ("ns",Body(1)),
+                ("def",Body(1)),
+                ("defn",Defn),
+                ("defn-",Defn),
+                ("defmacro",Defn),
+                ("defmethod",Body(2)),
+                ("defprotocol",Body(1)),
+                ("defrecord",Body(2)),
+                ("fn",Body(1)),
+                ("let",Body(1)),
+                ("loop",Body(1)),
+                ("binding",Body(1)),
+                ("if",Body(1)),
+                ("if-let",Body(1)),
+                ("when",Body(1)),
+                ("when-let",Body(1)),
+                ("when-not",Body(1)),
+                ("doseq",Body(1)),
+                ("dotimes",Body(1)),
+                ("case",Body(1)),
+                ("cond",Body(0)),
+                ("condp",Body(2)),
+                ("do",Body(0)),
+                ("try",Body(0)),
+                ("catch",Body(2)),
+                ("finally",Body(0)),
+            ]),
+            _=>return None,
+        };
+        Some(IndentRules {
+            name,
+            body:2,
+            default:Align,
+            def_forms:true,
+            align_data:true,
+            forms:forms.iter().map(|(head,rule)|(head.to_string(),*rule)).collect(),
+        })
+    }
+    /// The rule for a list starting with the symbol `head`.
+    pub fn rule(&self,head:&str)->Indent {
+        if let Some(rule)=self.forms.get(head) {
+            return *rule;
+        }
+        if self.def_forms&&head.starts_with("def") {
+            return Indent::Body(1);
+        }
+        self.default
+    }
+}
@@ -0,0 +1,644 @@
+//! A Wadler style pretty printer. Objects are turned into a `Doc` describing where lines may break,
+//! then the printer puts as much as fits within the line width on each line. The result is used
+//! both for saving and for drawing, so what is on screen is what gets written. The one difference is
+//! that strings holding newlines are drawn over several lines, while they are written with `\n`.
+use std::{
+    mem::take,
+    collections::HashMap,
+};
+use super::*;
+
+
+/// The line width used when nothing else is configured.
+pub const DEFAULT_WIDTH:usize=80;
+
+
+/// Everything that decides how objects are laid out.
+#[derive(Debug,Clone)]
+pub struct Format {
+    pub width:usize,
+    pub indent:IndentRules,
+}
+impl Default for Format {
+    fn default()->Self {
+        Format {
+            width:DEFAULT_WIDTH,
+            indent:IndentRules::default(),
+        }
+    }
+}
+
+
+/// Comments to lay out along with objects read from a text, by the byte offset of what they come
+/// before: the start of an item, or the close paren of a list.
+pub type Comments=HashMap<usize,Vec<Comment>>;
+/// A comment between the items of a list.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Comment {
+    pub text:String,
+    /// It was on the line of the item or open paren before it, so it stays there.
+    pub trailing:bool,
+}
+
+
+/// How a char of a string is written between the quotes, if it needs an escape.
+pub fn escape(c:char)->Option<&'static str> {
+    match c {
+        '"'=>Some("\\\""),
+        '\\'=>Some("\\\\"),
+        '\n'=>Some("\\n"),
+        '\t'=>Some("\\t"),
+        '\r'=>Some("\\r"),
+        '\0'=>Some("\\0"),
+        _=>None,
+    }
+}
+/// Escapes a string value to be written between quotes.
+pub fn escape_string(s:&str)->String {
+    let mut out=String::with_capacity(s.len());
+    for c in s.chars() {
+        match escape(c) {
+            Some(escaped)=>out.push_str(escaped),
+            None=>out.push(c),
+        }
+    }
+    out
+}
+
+
+/// What a piece of text is, so the renderer knows how to color it.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Mark {
+    /// An open paren and the nesting depth of its list.
+    Open(usize),
+    /// A close paren and the nesting depth of its list.
+    Close(usize),
+    Ident,
+    Number,
+    String,
+    Comment,
+}
+/// A position in the laid out text. Ordered by line first, so ranges follow the text.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Default)]
+pub struct Position {
+    pub line:usize,
+    pub column:usize,
+}
+/// A piece of text and where the printer put it.
+#[derive(Debug,Clone)]
+pub struct Piece {
+    pub position:Position,
+    pub text:String,
+    pub mark:Mark,
+    /// The index of the node the text belongs to in `Layout::nodes`.
+    pub node:usize,
+}
+/// Where a node ended up. `end` is the position just after the node.
+#[derive(Debug,Clone)]
+pub struct NodeSpan {
+    pub path:Vec<usize>,
+    pub start:Position,
+    pub end:Position,
+}
+impl NodeSpan {
+    /// Checks if the position lies between the start and end of the node, following the text
+    /// from line to line like a text selection does.
+    pub fn contains(&self,position:Position)->bool {
+        position>=self.start&&position<self.end
+    }
+}
+
+
+/// The description of a document that the printer lays out.
+pub enum Doc {
+    /// A piece of text that never contains a newline.
+    Text(String,Mark),
+    /// A space if the enclosing group fits on one line, otherwise a newline and indentation.
+    Line,
+    /// A space that never breaks.
+    Space,
+    /// A comment, put on a line of its own if the bool is set. Whatever comes after it goes on the
+    /// next line.
+    Comment(String,bool),
+    /// A newline inside a string drawn on screen. The next line starts at the first column, so the
+    /// string reads as it would be printed.
+    Newline,
+    Concat(Vec<Doc>),
+    /// Prints its contents on one line if they fit, otherwise breaks every `Line` directly in it.
+    Group(Box<Doc>),
+    /// Adds to the indentation used after broken lines.
+    Nest(usize,Box<Doc>),
+    /// Sets the indentation used after broken lines to the current column.
+    Align(Box<Doc>),
+    /// Everything printed by the inner document belongs to one object in the tree.
+    Node(Box<Doc>),
+}
+impl Doc {
+    /// Builds the document for an object nested `depth` lists deep. With `multiline` the newlines in
+    /// strings break the line instead of being escaped. `comments` are put between the items they
+    /// were between.
+    pub fn object(object:&Object,depth:usize,rules:&IndentRules,multiline:bool,comments:&Comments)->Self {
+        let inner=match object {
+            Object::List(items,delimiter,span)=>{
+                let open=Doc::Text(delimiter.open().into(),Mark::Open(depth));
+                let close=Doc::Text(delimiter.close().into(),Mark::Close(depth));
+                // vectors and maps are data even when they start with a symbol
+                let rule=match items.first() {
+                    _ if *delimiter!=Delimiter::Paren=>None,
+                    Some(object@Object::Ident(name,_)) if !object.is_reader_prefix()=>Some(rules.rule(name)),
+                    _ if rules.align_data=>None,
+                    _=>Some(rules.default),
+                };
+                let mut args=Doc::items(items,depth+1,rules,multiline,comments);
+                if let Some(trailing)=span.and_then(|span|comments.get(&(span.end-1))) {
+                    let last=args.pop().unwrap_or(Doc::Concat(Vec::new()));
+                    args.push(Doc::with_comments(last,trailing));
+                }
+                // aligned to the open paren, so a close paren after a comment goes under it
+                if args.len()<2 {
+                    let mut docs=vec![open];
+                    docs.extend(args);
+                    docs.push(close);
+                    return Doc::Node(Box::new(Doc::Align(Box::new(Doc::Concat(docs)))));
+                }
+                let head=args.remove(0);
+                match rule {
+                    // data: every item under the first one
+                    None=>{
+                        let mut items=vec![head];
+                        items.extend(Doc::lines(args));
+                        Doc::Align(Box::new(Doc::Group(Box::new(Doc::Concat(vec![
+                            open,
+                            Doc::Align(Box::new(Doc::Concat(items))),
+                            close,
+                        ])))))
+                    },
+                    Some(Indent::Align)=>{
+                        let mut args=args.into_iter();
+                        let mut aligned=vec![args.next().unwrap()];
+                        aligned.extend(Doc::lines(args));
+                        Doc::Align(Box::new(Doc::Group(Box::new(Doc::Concat(vec![
+                            open,
+                            head,
+                            // a comment after the head breaks this space like a special argument
+                            Doc::Nest(rules.body*2,Box::new(Doc::Space)),
+                            Doc::Align(Box::new(Doc::Concat(aligned))),
+                            close,
+                        ])))))
+                    },
+                    Some(rule)=>{
+                        let special=match rule {
+                            Indent::Body(special)=>special,
+                            // the docstring of a `defn` is special too
+                            _ if matches!(items.get(2),Some(Object::String(..)))=>3,
+                            _=>2,
+                        };
+                        let body=args.split_off(special.min(args.len()));
+                        let mut special=args.into_iter();
+                        let mut docs=vec![open,head];
+                        // the first special argument always stays with the head
+                        if let Some(first)=special.next() {
+                            docs.push(Doc::Nest(rules.body*2,Box::new(Doc::Space)));
+                            docs.push(first);
+                            docs.push(Doc::Group(Box::new(Doc::Nest(
+                                rules.body*2,
+                                Box::new(Doc::Concat(Doc::lines(special))),
+                            ))));
+                        }
+                        docs.push(Doc::Nest(
+                            rules.body,
+                            Box::new(Doc::Concat(Doc::lines(body))),
+                        ));
+                        docs.push(close);
+                        // indented from the open paren, so align to it first
+                        Doc::Align(Box::new(Doc::Group(Box::new(Doc::Concat(docs)))))
+                    },
+                }
+            },
+            Object::Ident(s,_)=>Doc::Text(s.clone(),Mark::Ident),
+            Object::Number(s,_)=>Doc::Text(s.clone(),Mark::Number),
+            Object::String(s,_) if multiline=>{
+                let text=format!("\"{}\"",s.split('\n').map(escape_string).collect::<Vec<_>>().join("\n"));
+                let mut lines=Vec::new();
+                for (i,line) in text.split('\n').enumerate() {
+                    if i>0 {
+                        lines.push(Doc::Newline);
+                    }
+                    lines.push(Doc::Text(line.to_string(),Mark::String));
+                }
+                Doc::Concat(lines)
+            },
+            Object::String(s,_)=>Doc::Text(format!("\"{}\"",escape_string(s)),Mark::String),
+        };
+        Doc::Node(Box::new(inner))
+    }
+    /// The documents for a run of objects. Reader prefixes like `'` are glued to the object after
+    /// them, so each document is one item as it is written. Comments before an item go before its
+    /// prefixes, unless they are trailing the item before.
+    fn items(items:&[Object],depth:usize,rules:&IndentRules,multiline:bool,comments:&Comments)->Vec<Self> {
+        let mut docs=Vec::new();
+        let mut prefixes=Vec::new();
+        for item in items {
+            if let Some(before)=item.span().and_then(|span|comments.get(&span.start)) {
+                let mut before=&before[..];
+                // where the comments on lines of their own go, ahead of any reader prefixes
+                let mut at=0;
+                if matches!(before.first(),Some(comment) if comment.trailing)&&prefixes.is_empty() {
+                    match docs.pop() {
+                        Some(last)=>docs.push(Doc::with_comments(last,&before[..1])),
+                        // right after the open paren
+                        None=>{
+                            prefixes.push(Doc::with_comments(Doc::Concat(Vec::new()),&before[..1]));
+                            at=1;
+                        },
+                    }
+                    before=&before[1..];
+                }
+                prefixes.splice(at..at,before.iter().map(|comment|Doc::Comment(comment.text.clone(),true)));
+            }
+            prefixes.push(Doc::object(item,depth,rules,multiline,comments));
+            if !item.is_reader_prefix() {
+                docs.push(Doc::Concat(take(&mut prefixes)));
+            }
+        }
+        if !prefixes.is_empty() {
+            docs.push(Doc::Concat(prefixes));
+        }
+        docs
+    }
+    /// Puts comments after a document. One that was on the line of the document stays there.
+    fn with_comments(doc:Self,comments:&[Comment])->Self {
+        let mut docs=vec![doc];
+        for comment in comments {
+            if comment.trailing {
+                docs.push(Doc::Space);
+            }
+            docs.push(Doc::Comment(comment.text.clone(),!comment.trailing));
+        }
+        Doc::Concat(docs)
+    }
+    /// Puts a line break before each document.
+    fn lines(docs:impl IntoIterator<Item=Self>)->Vec<Self> {
+        let mut lines=Vec::new();
+        for doc in docs {
+            lines.push(Doc::Line);
+            lines.push(doc);
+        }
+        lines
+    }
+}
+
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+enum Command<'doc> {
+    Print(usize,Mode,&'doc Doc),
+    /// Finishes the node with the given index.
+    Leave(usize),
+}
+
+
+/// A list of objects laid out within a line width.
+#[derive(Debug,Clone,Default)]
+pub struct Layout {
+    pub pieces:Vec<Piece>,
+    /// Every node in the order they start, so a node is followed by its descendants.
+    pub nodes:Vec<NodeSpan>,
+    /// The amount of lines used.
+    pub lines:usize,
+}
+impl Layout {
+    /// Lays out the top-level objects one after another, each starting on a new line.
+    pub fn new(objects:&[Object],format:&Format)->Self {
+        Self::build(objects,format,false)
+    }
+    /// Like `new`, but strings holding newlines take several lines, for drawing on screen.
+    pub fn screen(objects:&[Object],format:&Format)->Self {
+        Self::build(objects,format,true)
+    }
+    fn build(objects:&[Object],format:&Format,multiline:bool)->Self {
+        let mut printer=Printer {
+            width:format.width,
+            position:Position::default(),
+            path:vec![0],
+            open:Vec::new(),
+            layout:Layout::default(),
+            pending:false,
+        };
+        let comments=Comments::new();
+        for (i,object) in objects.iter().enumerate() {
+            printer.print(&Doc::object(object,0,&format.indent,multiline,&comments));
+            // reader prefixes are written right before the next object
+            if object.is_reader_prefix()&&i+1<objects.len() {
+                continue;
+            }
+            printer.position.line+=1;
+            printer.position.column=0;
+        }
+        printer.layout.lines=printer.position.line;
+        printer.layout
+    }
+    /// The text of the layout, with every line ended by a newline.
+    pub fn text(&self)->String {
+        let mut out=String::new();
+        let mut at=Position::default();
+        for piece in self.pieces.iter() {
+            while at.line<piece.position.line {
+                out.push('\n');
+                at.line+=1;
+                at.column=0;
+            }
+            while at.column<piece.position.column {
+                out.push(' ');
+                at.column+=1;
+            }
+            out.push_str(&piece.text);
+            at.column+=piece.text.chars().count();
+        }
+        while at.line<self.lines {
+            out.push('\n');
+            at.line+=1;
+        }
+        out
+    }
+    /// Lays out a single object as if it started at `column` of the first line. Its path is `[0]`.
+    pub fn at(object:&Object,column:usize,format:&Format)->Self {
+        Self::commented(object,column,format,&Comments::new())
+    }
+    /// Like `at`, putting `comments` between the items of the object they were between.
+    pub fn commented(object:&Object,column:usize,format:&Format,comments:&Comments)->Self {
+        let mut printer=Printer {
+            width:format.width,
+            position:Position{line:0,column},
+            path:vec![0],
+            open:Vec::new(),
+            layout:Layout::default(),
+            pending:false,
+        };
+        printer.print(&Doc::object(object,0,&format.indent,false,comments));
+        printer.layout.lines=printer.position.line+1;
+        printer.layout
+    }
+    /// The index of the node at `path` in `nodes`.
+    pub fn node(&self,path:&[usize])->Option<usize> {
+        self.nodes.iter().position(|node|node.path==path)
+    }
+    /// The deepest node containing `position`.
+    pub fn node_at(&self,position:Position)->Option<&NodeSpan> {
+        self.nodes.iter()
+            .filter(|node|node.contains(position))
+            .max_by_key(|node|node.path.len())
+    }
+}
+
+
+struct Printer {
+    width:usize,
+    position:Position,
+    /// The path of the next node to start.
+    path:Vec<usize>,
+    /// The indices of the nodes that are being printed, innermost last.
+    open:Vec<usize>,
+    layout:Layout,
+    /// A comment was printed, so the next thing goes on a new line.
+    pending:bool,
+}
+impl Printer {
+    /// Starts a new line at column `indent`.
+    fn newline(&mut self,indent:usize) {
+        self.position.line+=1;
+        self.position.column=indent;
+        self.pending=false;
+    }
+    /// Goes to a new line if a comment asked for one.
+    fn settle(&mut self,indent:usize) {
+        if self.pending {
+            self.newline(indent);
+        }
+    }
+    fn print(&mut self,doc:&Doc) {
+        let mut stack=vec![Command::Print(0,Mode::Break,doc)];
+        while let Some(command)=stack.pop() {
+            let (indent,mode,doc)=match command {
+                Command::Print(indent,mode,doc)=>(indent,mode,doc),
+                Command::Leave(node)=>{
+                    self.open.pop();
+                    self.path.pop();
+                    self.layout.nodes[node].end=self.position;
+                    if let Some(index)=self.path.last_mut() {
+                        *index+=1;
+                    }
+                    continue;
+                },
+            };
+            match doc {
+                Doc::Text(text,mark)=>{
+                    self.settle(indent);
+                    self.layout.pieces.push(Piece {
+                        position:self.position,
+                        text:text.clone(),
+                        mark:*mark,
+                        node:*self.open.last().unwrap(),
+                    });
+                    self.position.column+=text.chars().count();
+                },
+                Doc::Line if mode==Mode::Break=>self.newline(indent),
+                Doc::Line|Doc::Space=>if self.pending {
+                    self.newline(indent);
+                } else {
+                    self.position.column+=1;
+                },
+                Doc::Comment(text,own_line)=>{
+                    let fresh=!matches!(self.layout.pieces.last(),Some(piece) if piece.position.line==self.position.line);
+                    if *own_line&&!fresh {
+                        self.newline(indent);
+                    }
+                    self.layout.pieces.push(Piece {
+                        position:self.position,
+                        text:text.clone(),
+                        mark:Mark::Comment,
+                        node:*self.open.last().unwrap(),
+                    });
+                    self.position.column+=text.chars().count();
+                    self.pending=true;
+                },
+                Doc::Newline=>{
+                    self.position.line+=1;
+                    self.position.column=0;
+                },
+                Doc::Concat(docs)=>{
+                    for doc in docs.iter().rev() {
+                        stack.push(Command::Print(indent,mode,doc));
+                    }
+                },
+                Doc::Group(inner)=>{
+                    let mode=if mode==Mode::Flat||self.fits(inner,indent,&stack) {
+                        Mode::Flat
+                    } else {
+                        Mode::Break
+                    };
+                    stack.push(Command::Print(indent,mode,inner));
+                },
+                Doc::Nest(amount,inner)=>stack.push(Command::Print(indent+amount,mode,inner)),
+                // after a comment, whatever comes next starts the next line at `indent`
+                Doc::Align(inner)=>{
+                    let column=if self.pending {indent} else {self.position.column};
+                    stack.push(Command::Print(column,mode,inner));
+                },
+                Doc::Node(inner)=>{
+                    self.settle(indent);
+                    let node=self.layout.nodes.len();
+                    self.layout.nodes.push(NodeSpan {
+                        path:self.path.clone(),
+                        start:self.position,
+                        end:self.position,
+                    });
+                    self.path.push(0);
+                    self.open.push(node);
+                    stack.push(Command::Leave(node));
+                    stack.push(Command::Print(indent,mode,inner));
+                },
+            }
+        }
+    }
+    /// Checks if `doc` fits on the rest of the line when printed flat, along with whatever comes
+    /// after it up to the next line break. A comment ends the line, so `doc` only fits if it ends
+    /// with the comment.
+    fn fits(&self,doc:&Doc,indent:usize,rest:&[Command])->bool {
+        let column=if self.pending {indent} else {self.position.column};
+        let mut remaining=self.width as isize-column as isize;
+        let mut look=vec![(Mode::Flat,doc)];
+        let mut rest=rest.iter().rev();
+        let mut after=false;
+        loop {
+            if remaining<0 {
+                return false;
+            }
+            let (mode,doc)=match look.pop() {
+                Some(item)=>item,
+                None=>match rest.next() {
+                    Some(Command::Print(_,mode,doc))=>{
+                        after=true;
+                        (*mode,*doc)
+                    },
+                    Some(Command::Leave(_))=>continue,
+                    None=>return true,
+                },
+            };
+            match doc {
+                Doc::Text(text,_)=>remaining-=text.chars().count() as isize,
+                Doc::Line=>match mode {
+                    Mode::Flat=>remaining-=1,
+                    Mode::Break=>return true,
+                },
+                Doc::Space=>remaining-=1,
+                Doc::Newline=>return true,
+                Doc::Comment(..)=>return after||look.is_empty(),
+                Doc::Concat(docs)=>look.extend(docs.iter().rev().map(|doc|(mode,doc))),
+                // groups after the one being measured may still break, so they keep the mode
+                // of what they are in
+                Doc::Group(inner)|Doc::Nest(_,inner)|Doc::Align(inner)|Doc::Node(inner)=>look.push((mode,inner)),
+            }
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source:&str)->Vec<Object> {
+        Object::parse_file(source,Dialect::generic()).unwrap()
+    }
+    fn parse_clojure(source:&str)->Vec<Object> {
+        Object::parse_file(source,Dialect::named("clojure").unwrap()).unwrap()
+    }
+    fn format(indent:&str,width:usize)->Format {
+        Format {
+            width,
+            indent:IndentRules::named(indent).unwrap(),
+        }
+    }
+    fn ident(s:&str)->Object {
+        Object::Ident(s.to_string(),None)
+    }
+
+    #[test]
+    fn fits_on_one_line() {
+        let objects=parse("(a b c) (d)");
+        assert_eq!(Layout::new(&objects,&format("default",80)).text(),"(a b c)\n(d)\n");
+    }
+    #[test]
+    fn breaks_when_too_wide() {
+        let objects=parse("(foo aaaa bbbb)");
+        assert_eq!(Layout::new(&objects,&format("default",15)).text(),"(foo aaaa bbbb)\n");
+        assert_eq!(Layout::new(&objects,&format("default",14)).text(),"(foo\n    aaaa\n    bbbb)\n");
+    }
+    #[test]
+    fn body_rule() {
+        let objects=parse("(define (f x) (g x) (h x))");
+        assert_eq!(Layout::new(&objects,&format("scheme",16)).text(),"(define (f x)\n  (g x)\n  (h x))\n");
+        // special arguments that don't fit get twice the body indent
+        let objects=parse("(do ((i 0 (+ i 1))) ((= i 10)) (display i))");
+        assert_eq!(Layout::new(&objects,&format("scheme",20)).text(),"(do ((i 0 (+ i 1)))\n    ((= i 10))\n  (display i))\n");
+    }
+    #[test]
+    fn defn_rule() {
+        let objects=parse_clojure("(defn foo [x y] (+ x y) (* x y))");
+        assert_eq!(Layout::new(&objects,&format("clojure",20)).text(),"(defn foo [x y]\n  (+ x y)\n  (* x y))\n");
+        let objects=parse_clojure("(defn foo \"Adds.\" [x y] (+ x y))");
+        assert_eq!(Layout::new(&objects,&format("clojure",30)).text(),"(defn foo \"Adds.\" [x y]\n  (+ x y))\n");
+    }
+    #[test]
+    fn vectors_and_maps_are_data() {
+        let objects=parse_clojure("[a 1 b 2]\n{:a a :b b}");
+        assert_eq!(Layout::new(&objects,&format("clojure",8)).text(),"[a\n 1\n b\n 2]\n{:a\n a\n :b\n b}\n");
+        assert_eq!(Layout::new(&objects,&format("default",8)).text(),"[a\n 1\n b\n 2]\n{:a\n a\n :b\n b}\n");
+        let objects=parse_clojure("(let [a 1 b 2] a)");
+        assert_eq!(Layout::new(&objects,&format("clojure",16)).text(),"(let [a 1 b 2]\n  a)\n");
+    }
+    #[test]
+    fn align_rule() {
+        let objects=parse("(if aaaa bbbb cccc)");
+        assert_eq!(Layout::new(&objects,&format("scheme",12)).text(),"(if aaaa\n    bbbb\n    cccc)\n");
+    }
+    #[test]
+    fn reader_prefixes_stay_with_their_object() {
+        let objects=vec![ident("'"),Object::List(vec![ident("a"),ident("b")],Delimiter::Paren,None)];
+        let layout=Layout::new(&objects,&format("default",80));
+        assert_eq!(layout.text(),"'(a b)\n");
+        assert_eq!(layout.nodes[layout.node(&[1]).unwrap()].start,Position{line:0,column:1});
+        let objects=vec![Object::List(vec![ident("list"),ident("'"),ident("aaaa"),ident("'"),ident("bbbb")],Delimiter::Paren,None)];
+        assert_eq!(Layout::new(&objects,&format("scheme",12)).text(),"(list 'aaaa\n      'bbbb)\n");
+    }
+    #[test]
+    fn strings_are_escaped() {
+        let objects=vec![Object::String("a \"b\"\n\tc\\".to_string(),None)];
+        assert_eq!(Layout::new(&objects,&format("default",80)).text(),"\"a \\\"b\\\"\\n\\tc\\\\\"\n");
+        // only the screen shows newlines as they are
+        let layout=Layout::screen(&objects,&format("default",80));
+        assert_eq!(layout.text(),"\"a \\\"b\\\"\n\\tc\\\\\"\n");
+        assert_eq!(layout.lines,2);
+    }
+    #[test]
+    fn node_positions() {
+        let objects=parse("(aaaaaa (b c))\n(d\n e)");
+        let layout=Layout::new(&objects,&format("default",12));
+        assert_eq!(layout.text(),"(aaaaaa\n    (b c))\n(d e)\n");
+        let node=&layout.nodes[layout.node(&[0,1]).unwrap()];
+        assert_eq!((node.start,node.end),(Position{line:1,column:4},Position{line:1,column:9}));
+        let node=&layout.nodes[layout.node(&[1,1]).unwrap()];
+        assert_eq!((node.start,node.end),(Position{line:2,column:3},Position{line:2,column:4}));
+        assert_eq!(layout.node_at(Position{line:1,column:5}).unwrap().path,vec![0,1,0]);
+        assert_eq!(layout.node_at(Position{line:1,column:6}).unwrap().path,vec![0,1]);
+        // nodes follow the text, so the end of a line belongs to the list that goes on after it
+        assert_eq!(layout.node_at(Position{line:0,column:10}).unwrap().path,vec![0]);
+        assert_eq!(layout.node_at(Position{line:1,column:9}).unwrap().path,vec![0]);
+        assert!(layout.node_at(Position{line:1,column:10}).is_none());
+    }
+}
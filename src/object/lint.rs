@@ -0,0 +1,208 @@
+//! Checks for mistakes that the tree can show without running anything: atoms that read back as
+//! something else, definitions that shadow each other, unused bindings and special forms with the
+//! wrong amount of arguments.
+use std::collections::HashSet;
+use super::*;
+
+
+/// Forms that bind names for their body.
+const LET_FORMS:&[&str]=&["let","let*","letrec","letrec*"];
+
+
+/// Finds the problems in the top-level objects, in the order they appear.
+pub fn lint(objects:&[Object],dialect:&Dialect)->Vec<Diagnostic> {
+    let mut linter=Linter {
+        dialect,
+        path:Vec::new(),
+        diagnostics:Vec::new(),
+    };
+    linter.items(objects,false);
+    linter.definitions(objects);
+    linter.diagnostics.sort_by(|a,b|a.path.cmp(&b.path));
+    linter.diagnostics
+}
+
+
+struct Linter<'a> {
+    dialect:&'a Dialect,
+    /// The path of the object being checked.
+    path:Vec<usize>,
+    diagnostics:Vec<Diagnostic>,
+}
+impl<'a> Linter<'a> {
+    fn report(&mut self,path:Vec<usize>,severity:Severity,message:String) {
+        self.diagnostics.push(Diagnostic{path,severity,message});
+    }
+    /// Checks a run of siblings. Inside quoted data lists are not calls, so they are not checked
+    /// as special forms.
+    fn items(&mut self,items:&[Object],quoted:bool) {
+        for (i,item) in items.iter().enumerate() {
+            let quoted=quoted||matches!(i.checked_sub(1).map(|before|&items[before]),Some(Object::Ident(prefix,_)) if prefix=="'"||prefix=="`");
+            self.path.push(i);
+            self.object(item,quoted);
+            self.path.pop();
+        }
+    }
+    fn object(&mut self,object:&Object,quoted:bool) {
+        match object {
+            Object::List(items,..)=>{
+                let head=match items.first() {
+                    Some(Object::Ident(head,_)) if !quoted=>Some(head.as_str()),
+                    _=>None,
+                };
+                if let Some(head)=head {
+                    self.arguments(head,items.len()-1);
+                    if LET_FORMS.contains(&head) {
+                        self.bindings(items);
+                    }
+                }
+                self.items(items,quoted||head==Some("quote"));
+            },
+            atom=>if let Some(problem)=atom.problem() {
+                self.report(self.path.clone(),Severity::Error,problem);
+            },
+        }
+    }
+    fn arguments(&mut self,head:&str,count:usize) {
+        let (min,max)=match self.dialect.arities.iter().find(|(form,..)|*form==head) {
+            Some((_,min,max))=>(*min,*max),
+            None=>return,
+        };
+        if count>=min&&!matches!(max,Some(max) if count>max) {
+            return;
+        }
+        let expected=match max {
+            None=>format!("at least {}",min),
+            Some(max) if max==min=>min.to_string(),
+            Some(max)=>format!("{} to {}",min,max),
+        };
+        let mut path=self.path.clone();
+        path.push(0);
+        self.report(path,Severity::Error,format!("`{}` takes {} arguments, not {}",head,expected,count));
+    }
+    /// Warns about the names a let form binds that its body never uses. Bindings are either a
+    /// list of `(name value)` lists and bare names, or a flat `[name value ...]` vector. Names in
+    /// a vector that aren't symbols destructure the value, and are left alone.
+    fn bindings(&mut self,items:&[Object]) {
+        let (bindings,delimiter)=match items.get(1) {
+            Some(Object::List(bindings,delimiter,_))=>(bindings,*delimiter),
+            _=>return,
+        };
+        let flat=delimiter==Delimiter::Bracket;
+        let names=bindings.iter()
+            .enumerate()
+            .filter_map(|(i,binding)|match binding {
+                Object::Ident(name,_) if !flat||i%2==0=>Some((vec![1,i],name)),
+                Object::List(pair,..) if !flat=>match pair.first() {
+                    Some(Object::Ident(name,_))=>Some((vec![1,i,0],name)),
+                    _=>None,
+                },
+                _=>None,
+            })
+            .filter(|(_,name)|!name.starts_with('_')&&name.as_str()!="&");
+        for (at,name) in names {
+            // the name itself is one use
+            if items.iter().map(|item|uses(item,name)).sum::<usize>()<=1 {
+                let mut path=self.path.clone();
+                path.extend(at);
+                self.report(path,Severity::Warning,format!("`{}` is never used",name));
+            }
+        }
+    }
+    /// Warns about top-level definitions of a name that was already defined.
+    fn definitions(&mut self,objects:&[Object]) {
+        let mut defined=HashSet::new();
+        for (i,object) in objects.iter().enumerate() {
+            // methods add to a generic function, so they share its name
+            if let Object::List(items,..)=object {
+                if matches!(items.first(),Some(Object::Ident(head,_)) if head=="defmethod") {
+                    continue;
+                }
+            }
+            let path=match [vec![i,1],vec![i,1,0]].into_iter().find(|path|self.dialect.highlight(objects,path)==Some(Highlight::Definition)) {
+                Some(path)=>path,
+                None=>continue,
+            };
+            if let Some(Object::Ident(name,_))=object_at(objects,&path) {
+                if !defined.insert(name.as_str()) {
+                    self.report(path,Severity::Warning,format!("`{}` is already defined",name));
+                }
+            }
+        }
+    }
+}
+/// How many times `name` appears as a symbol in `object`.
+fn uses(object:&Object,name:&str)->usize {
+    match object {
+        Object::Ident(symbol,_)=>(symbol==name) as usize,
+        Object::List(items,..)=>items.iter().map(|item|uses(item,name)).sum(),
+        _=>0,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint_source(source:&str,dialect:&str)->Vec<(Vec<usize>,Severity,String)> {
+        let dialect=Dialect::named(dialect).unwrap();
+        let objects=Object::parse_file(source,dialect).unwrap();
+        lint(&objects,dialect).into_iter()
+            .map(|diagnostic|(diagnostic.path,diagnostic.severity,diagnostic.message))
+            .collect()
+    }
+
+    #[test]
+    fn invalid_atoms() {
+        let objects=vec![Object::List(vec![
+            Object::Ident("f".to_string(),None),
+            Object::Number("12".to_string(),None),
+            Object::Number("1x".to_string(),None),
+            Object::Ident(String::new(),None),
+        ],Delimiter::Paren,None)];
+        let paths:Vec<_>=lint(&objects,Dialect::generic()).into_iter()
+            .map(|diagnostic|(diagnostic.path,diagnostic.severity))
+            .collect();
+        assert_eq!(paths,vec![(vec![0,2],Severity::Error),(vec![0,3],Severity::Error)]);
+    }
+    #[test]
+    fn arity() {
+        assert_eq!(lint_source("(if a)","scheme"),vec![
+            (vec![0,0],Severity::Error,"`if` takes 2 to 3 arguments, not 1".to_string()),
+        ]);
+        assert_eq!(lint_source("(set! a b c)","scheme")[0].2,"`set!` takes 2 arguments, not 3");
+        assert_eq!(lint_source("(lambda)","scheme")[0].2,"`lambda` takes at least 2 arguments, not 0");
+        assert!(lint_source("(if a b) (if a b c) (set! a 1)","scheme").is_empty());
+        // quoted lists are data, not calls
+        assert!(lint_source("'(if a) (quote (if a))","scheme").is_empty());
+        // other dialects have other forms
+        assert!(lint_source("(if a)","generic").is_empty());
+    }
+    #[test]
+    fn unused_bindings() {
+        assert_eq!(lint_source("(let ((x 1) (y 2)) x)","scheme"),vec![
+            (vec![0,1,1,0],Severity::Warning,"`y` is never used".to_string()),
+        ]);
+        assert!(lint_source("(let ((x 1) (_y 2)) (f x))","scheme").is_empty());
+        assert_eq!(lint_source("(let [x 1 y x] y)","clojure"),Vec::new());
+        assert_eq!(lint_source("(let [x 1 y 2] y)","clojure"),vec![
+            (vec![0,1,0],Severity::Warning,"`x` is never used".to_string()),
+        ]);
+        // values that are calls aren't `(name value)` pairs
+        assert_eq!(lint_source("(let [x (inc 1)] x)","clojure"),Vec::new());
+        assert_eq!(lint_source("(let [x (inc 1) y (dec 1)] x)","clojure"),vec![
+            (vec![0,1,2],Severity::Warning,"`y` is never used".to_string()),
+        ]);
+        assert_eq!(lint_source("(let [{:keys [a]} m] a)","clojure"),Vec::new());
+        assert_eq!(lint_source("(let [[a b] pair c (f a)] c)","clojure"),Vec::new());
+    }
+    #[test]
+    fn duplicate_definitions() {
+        assert_eq!(lint_source("(define (f) 1)\n(define (g) 2)\n(define (f) 3)","scheme"),vec![
+            (vec![2,1,0],Severity::Warning,"`f` is already defined".to_string()),
+        ]);
+        assert!(lint_source("(define (f) 1)\n(define (g) 2)","scheme").is_empty());
+        assert!(lint_source("(defgeneric area (shape))\n(defmethod area ((s square)) 1)\n(defmethod area ((c circle)) 2)","lisp").is_empty());
+    }
+}
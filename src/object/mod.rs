@@ -1,10 +1,6 @@
-use s_expression_parser::Object as SObject;
-use tui::{
-    style::{
-        Style,
-        Color,
-    },
-    buffer::Buffer,
+use s_expression_parser::{
+    Object as SObject,
+    File as SFile,
 };
 use std::{
     fmt::{
@@ -16,9 +12,78 @@ use std::{
 };
 use crate::colors::*;
 pub use widget::*;
+pub use canvas::*;
+pub use layout::*;
+pub use indent::*;
+pub use preserve::*;
+pub use dialect::*;
+pub use diagnostic::*;
+pub use source_map::*;
+pub use lint::*;
+pub use validate::{
+    KINDS,
+    convert_at,
+};
 
 
 mod widget;
+mod canvas;
+mod layout;
+mod indent;
+mod preserve;
+mod dialect;
+mod diagnostic;
+mod source_map;
+mod lint;
+mod validate;
+
+
+/// Serializes the top-level objects the same way they are written to a file and shown on screen.
+pub fn serialize(objects:&[Object],format:&Format)->String {
+    Layout::new(objects,format).text()
+}
+
+/// Finds the object at `path`, where the first index picks one of the top-level objects.
+pub fn object_at<'a>(objects:&'a [Object],path:&[usize])->Option<&'a Object> {
+    let (first,rest)=path.split_first()?;
+    let mut object=objects.get(*first)?;
+    for i in rest {
+        match object {
+            Object::List(items,..)=>object=items.get(*i)?,
+            _=>return None,
+        }
+    }
+    Some(object)
+}
+/// Like `object_at`, but the object can be changed.
+pub fn object_at_mut<'a>(objects:&'a mut [Object],path:&[usize])->Option<&'a mut Object> {
+    let (first,rest)=path.split_first()?;
+    let mut object=objects.get_mut(*first)?;
+    for i in rest {
+        match object {
+            Object::List(items,..)=>object=items.get_mut(*i)?,
+            _=>return None,
+        }
+    }
+    Some(object)
+}
+
+fn mark_edited(span:&mut Option<Span>) {
+    if let Some(span)=span {
+        span.edited=true;
+    }
+}
+/// Sets the start of the gap before each object to the end of the one before it. `start` is where
+/// the gap of the first object starts.
+fn link_gaps(objects:&mut [Object],start:usize) {
+    let mut gap=start;
+    for object in objects.iter_mut() {
+        if let Some(span)=object.span_mut() {
+            span.gap=gap;
+            gap=span.end;
+        }
+    }
+}
 
 
 pub enum CursorValidReason {
@@ -32,76 +97,171 @@ pub enum CursorValidReason {
     /// The search was NOT valid and the last n positions do not exist.
     DoesNotExist(usize),
 }
+/// Where an object was in the text it was read from.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Span {
+    /// The start of the whitespace and comments between the object and whatever was before it.
+    pub gap:usize,
+    pub start:usize,
+    pub end:usize,
+    /// Where the whitespace and comments after the last item of a list start. The same as `end`
+    /// for atoms, and for lists that took the place of an atom.
+    pub tail:usize,
+    /// The object itself was changed since it was read: an atom's text, or a list's items.
+    pub edited:bool,
+}
+impl Span {
+    fn new(start:usize,end:usize)->Self {
+        Span {
+            gap:start,
+            start,
+            end,
+            tail:end,
+            edited:false,
+        }
+    }
+    /// Checks if the span is where a list was read from, so its delimiters are in the text.
+    pub fn is_list(&self)->bool {
+        self.tail<self.end
+    }
+}
+/// An object in the tree. The `Span` is where it came from in the file, or `None` for objects
+/// created in the editor.
 #[derive(Debug)]
 pub enum Object {
-    List(Vec<Self>),
-    Ident(String),
-    Number(String),
-    String(String),
+    List(Vec<Self>,Delimiter,Option<Span>),
+    Ident(String,Option<Span>),
+    Number(String,Option<Span>),
+    String(String,Option<Span>),
 }
 impl<'input> From<SObject<'input>> for Object {
     fn from(o:SObject<'input>)->Self {
         match o {
-           SObject::Ident(_,i,_)=>Self::Ident(i.to_string()),
-           SObject::String(_,mut s,_)=>{
-               s=s.replace('\\',"\\\\");
-               s=s.replace('\n',"\\n");
-               s=s.replace('\r',"\\r");
-               s=s.replace('\t',"\\t");
-               s=s.replace('"',"\\\"");
-               s=s.replace('\0',"\\0");
-               Self::String(s)
+           SObject::Ident(start,i,end)=>Self::Ident(i.to_string(),Some(Span::new(start,end))),
+           // the string is kept as its value and only escaped when it is written
+           SObject::String(start,s,end)=>Self::String(s.to_string(),Some(Span::new(start,end))),
+           SObject::Number(start,n,end)=>Self::Number(n.to_string(),Some(Span::new(start,end))),
+           SObject::List(start,items,end)=>{
+               let mut items:Vec<Self>=items.into_iter().map(|i|i.into()).collect();
+               link_gaps(&mut items,start+1);
+               let tail=items.last().and_then(Self::span).map_or(start+1,|span|span.end);
+               Self::List(items,Delimiter::Paren,Some(Span{tail,..Span::new(start,end)}))
            },
-           SObject::Number(_,n,_)=>Self::Number(n.to_string()),
-           SObject::List(_,items,_)=>Self::List(items.into_iter().map(|i|i.into()).collect()),
         }
     }
 }
 impl Display for Object {
     fn fmt(&self,f:&mut Formatter)->FmtResult {
-        let indent=f.width().unwrap_or(0);
-        for _ in 0..indent {write!(f," ")?}
+        let text=Layout::new(std::slice::from_ref(self),&Format::default()).text();
+        f.write_str(text.strip_suffix('\n').unwrap_or(&text))
+    }
+}
+impl Object {
+    pub fn span(&self)->Option<&Span> {
         match self {
-            Self::List(items)=>{
-                match items.as_slice() {
-                    [first,last]=>{
-                        match last {
-                            Self::List(_)=>{
-                                writeln!(f,"({}",first)?;
-                                write!(f,"{:1$})",last,indent+4)
-                            },
-                            _=>write!(f,"({} {})",first,last),
-                        }
-                    },
-                    [first,rest@..]=>{
-                        write!(f,"({}",first)?;
-                        for i in rest {
-                            writeln!(f)?;
-                            write!(f,"{:1$}",i,indent+4)?;
-                        }
-                        write!(f,")")
-                    },
-                    []=>{
-                        write!(f,"()")
-                    },
+            Self::List(_,_,span)|
+                Self::Ident(_,span)|
+                Self::Number(_,span)|
+                Self::String(_,span)=>span.as_ref(),
+        }
+    }
+    pub fn span_mut(&mut self)->Option<&mut Span> {
+        match self {
+            Self::List(_,_,span)|
+                Self::Ident(_,span)|
+                Self::Number(_,span)|
+                Self::String(_,span)=>span.as_mut(),
+        }
+    }
+    /// Checks if the object is a symbol like `'` or `#` that is glued to the object after it.
+    pub fn is_reader_prefix(&self)->bool {
+        matches!(self,Self::Ident(s,_) if is_reader_prefix(s))
+    }
+    /// Checks that neither the object nor anything in it was changed since it was read.
+    pub fn is_pristine(&self)->bool {
+        match (self,self.span()) {
+            (_,None)=>false,
+            (_,Some(span)) if span.edited=>false,
+            (Self::List(items,..),_)=>items.iter().all(Self::is_pristine),
+            _=>true,
+        }
+    }
+    /// Takes the spans of an object parsed from a newer version of the text. Parts of the tree
+    /// that don't match anymore forget their spans.
+    pub fn adopt_spans(&mut self,other:Self) {
+        match (self,other) {
+            (Self::List(items,_,span),Self::List(others,_,other_span)) if items.len()==others.len()=>{
+                *span=other_span;
+                for (item,other) in items.iter_mut().zip(others) {
+                    item.adopt_spans(other);
                 }
             },
-            Self::Ident(s)|Self::Number(s)=>f.write_str(s),
-            Self::String(s)=>write!(f,"\"{}\"",s),
+            (Self::Ident(_,span),Self::Ident(_,other_span))|
+                (Self::Number(_,span),Self::Number(_,other_span))|
+                (Self::String(_,span),Self::String(_,other_span))=>*span=other_span,
+            (this,_)=>this.forget_spans(),
+        }
+    }
+    /// Drops the spans of the object and everything in it, so it is laid out from scratch.
+    pub fn forget_spans(&mut self) {
+        match self {
+            Self::List(items,_,span)=>{
+                *span=None;
+                items.iter_mut().for_each(Self::forget_spans);
+            },
+            Self::Ident(_,span)|Self::Number(_,span)|Self::String(_,span)=>*span=None,
+        }
+    }
+    /// Forgets the spans of everything in a list but keeps its own, so saving with
+    /// `serialize_preserving` lays it out again where it stands.
+    pub fn forget_item_spans(&mut self) {
+        if let Self::List(items,_,span)=self {
+            mark_edited(span);
+            items.iter_mut().for_each(Self::forget_spans);
+        }
+    }
+    /// Parses a whole file into a list of top-level objects, reading it as `dialect`.
+    pub fn parse_file(source:&str,dialect:&Dialect)->Result<Vec<Self>,String> {
+        let (masked,_)=dialect.mask(source);
+        match SFile::parse_file(&masked) {
+            Ok(file)=>{
+                let mut objects:Vec<Self>=file.items.into_iter().map(|o|o.into()).collect();
+                link_gaps(&mut objects,0);
+                objects.iter_mut().for_each(|object|object.unmask(source));
+                Ok(objects)
+            },
+            Err(e)=>Err(format!("{:?}",e)),
+        }
+    }
+    /// Parses text that isn't the file being edited, like something typed or pasted. The objects
+    /// have no spans, since they don't come from the file.
+    pub fn parse_snippet(source:&str,dialect:&Dialect)->Result<Vec<Self>,String> {
+        let mut objects=Self::parse_file(source,dialect)?;
+        objects.iter_mut().for_each(Self::forget_spans);
+        Ok(objects)
+    }
+    /// Puts back what `Dialect::mask` changed, using the spans to find the original text: the
+    /// delimiters of lists and the exact text of symbols.
+    fn unmask(&mut self,source:&str) {
+        match self {
+            Self::List(items,delimiter,Some(span))=>{
+                *delimiter=Delimiter::of(source[span.start..].chars().next());
+                items.iter_mut().for_each(|item|item.unmask(source));
+            },
+            Self::Ident(s,Some(span))|Self::Number(s,Some(span))=>*s=source[span.start..span.end].to_string(),
+            _=>{},
         }
     }
-}
-impl Object {
     pub fn is_cursor_valid(&self,cursor:&[usize])->CursorValidReason {
         use CursorValidReason::*;
         if cursor.len()==1 {
             match self {
-                Self::List(items)=>if items.len()>=cursor[0]||(items.len()==0&&cursor[0]==0) {
+                Self::List(items,..)=>if items.len()>=cursor[0]||(items.len()==0&&cursor[0]==0) {
                     Valid(items.len())
                 } else {
                     OutOfRange(cursor[0]-items.len())
                 },
-                Self::Ident(s)|Self::String(s)|Self::Number(s)=>{
+                Self::Ident(s,_)|Self::String(s,_)|Self::Number(s,_)=>{
                     let count=s.chars().count();
                     if count>=cursor[0]||(count==0&&cursor[0]==0) {
                         Edit(count)
@@ -112,12 +272,12 @@ impl Object {
             }
         } else if cursor.len()==0 {
             match self {
-                Self::List(items)=>Valid(items.len()),
-                Self::Ident(s)|Self::String(s)|Self::Number(s)=>Valid(s.chars().count()),
+                Self::List(items,..)=>Valid(items.len()),
+                Self::Ident(s,_)|Self::String(s,_)|Self::Number(s,_)=>Valid(s.chars().count()),
             }
         } else {
             match self {
-                Self::List(items)=>{
+                Self::List(items,..)=>{
                     if items.len()<=cursor[0] {
                         return DoesNotExist(cursor.len()-1);
                     }
@@ -132,7 +292,8 @@ impl Object {
     pub fn add_object(&mut self,cursor:&[usize],obj:Self) {
         if cursor.len()<=1 {
             match self {
-                Self::List(items)=>{
+                Self::List(items,_,span)=>{
+                    mark_edited(span);
                     if cursor.len()==0 {
                         items.push(obj);
                     } else {
@@ -144,10 +305,12 @@ impl Object {
                     }
                 },
                 item=>{
-                    let mut old_item=Self::List(Vec::new());
+                    // the new list takes the place of the atom in the text
+                    let span=item.span().map(|span|Span{edited:true,..*span});
+                    let mut old_item=Self::List(Vec::new(),Delimiter::Paren,span);
                     swap(item,&mut old_item);
                     match item {
-                        Self::List(items)=>{
+                        Self::List(items,..)=>{
                             items.push(old_item);
                             items.push(obj);
                         },
@@ -157,7 +320,7 @@ impl Object {
             }
         } else {
             match self {
-                Self::List(items)=>items[cursor[0]].add_object(&cursor[1..],obj),
+                Self::List(items,..)=>items[cursor[0]].add_object(&cursor[1..],obj),
                 _=>todo!("invalid cursor position"),
             }
         }
@@ -165,8 +328,9 @@ impl Object {
     pub fn add_char(&mut self,cursor:&[usize],c:char) {
         if cursor.len()<=1 {
             match self {
-                Self::List(_)=>todo!("Attempt to add a char to a list of objects"),
-                Self::Ident(s)|Self::String(s)|Self::Number(s)=>{
+                Self::List(..)=>todo!("Attempt to add a char to a list of objects"),
+                Self::Ident(s,span)|Self::String(s,span)|Self::Number(s,span)=>{
+                    mark_edited(span);
                     if cursor.len()==0 {
                         s.push(c);
                     } else {
@@ -181,21 +345,30 @@ impl Object {
             }
         } else {
             match self {
-                Self::List(items)=>items[cursor[0]].add_char(&cursor[1..],c),
+                Self::List(items,..)=>items[cursor[0]].add_char(&cursor[1..],c),
                 _=>todo!("invalid cursor position"),
             }
         }
     }
+    /// Replaces the text of an atom. Lists are left alone.
+    pub fn set_text(&mut self,text:&str) {
+        if let Self::Ident(s,span)|Self::String(s,span)|Self::Number(s,span)=self {
+            mark_edited(span);
+            *s=text.to_string();
+        }
+    }
     pub fn remove(&mut self,cursor:&[usize]) {
         if cursor.len()==1 {
             match self {
-                Self::List(items)=>{
+                Self::List(items,_,span)=>{
                     if items.len()>0 {
                         items.remove(cursor[0]);
+                        mark_edited(span);
                     }
                 },
-                Self::Ident(s)|Self::String(s)|Self::Number(s)=>{
+                Self::Ident(s,span)|Self::String(s,span)|Self::Number(s,span)=>{
                     if s.len()>0 {  // dont allow the last char to be removed
+                        mark_edited(span);
                         if cursor[0]==0 {
                             s.remove(0);
                         } else {
@@ -210,532 +383,9 @@ impl Object {
             // do nothing, because there is nothing to do
         } else {
             match self {
-                Self::List(items)=>items[cursor[0]].remove(&cursor[1..]),
+                Self::List(items,..)=>items[cursor[0]].remove(&cursor[1..]),
                 _=>todo!("invalid cursor position"),
             }
         }
     }
-    pub fn render(&self,colors:&Colors,line:&mut u16,level:u16,offset:u16,buf:&mut Buffer,cursor:Option<&[usize]>)->u16 {
-        match self {
-            Self::Ident(s)=>{
-                let indent=(level*4)+offset;
-                let s=if s.len()==0 {
-                    "(I)"
-                } else {
-                    s.as_str()
-                };
-                if let Some(cursor)=cursor {
-                    if cursor.len()==1 {
-                        if cursor[0]==s.chars().count() {
-                            let last=buf.set_stringn(
-                                indent, // make the indent 4 spaces
-                                *line,
-                                s,
-                                s.len(),
-                                Style::reset()
-                                    .fg(colors.ident)
-                            ).0;
-                            buf.get_mut(last,*line).bg=colors.ident;
-                            buf.get_mut(last,*line).fg=Color::Rgb(0,0,0);
-                            return last;
-                        } else {
-                            let mut cindices=s.char_indices().skip(cursor[0]);
-                            let index=cindices.next().expect("Invalid cursor position").0;
-                            let after=cindices.next().unwrap_or((s.len(),' ')).0;
-                            let cursor_style=Style::reset()
-                                .fg(Color::Rgb(0,0,0))
-                                .bg(colors.ident);
-                            let other_style=Style::reset()
-                                .fg(colors.ident);
-                            let mut last_char=buf.set_stringn(
-                                indent, // make the indent 4 spaces
-                                *line,
-                                &s[..index],
-                                s.len(),
-                                other_style,
-                            ).0;
-                            last_char=buf.set_stringn(
-                                last_char,  // make the indent 4 spaces
-                                *line,
-                                &s[index..after],
-                                s.len(),
-                                cursor_style,
-                            ).0;
-                            buf.set_stringn(
-                                last_char,  // make the indent 4 spaces
-                                *line,
-                                &s[after..],
-                                s.len(),
-                                other_style,
-                            ).0
-                        }
-                    } else {
-                        buf.set_stringn(
-                            indent, // make the indent 4 spaces
-                            *line,
-                            s,
-                            s.len(),
-                            Style::reset()
-                                .fg(Color::Rgb(0,0,0))
-                                .bg(colors.ident)
-                        ).0
-                    }
-                } else {
-                    buf.set_stringn(
-                        indent, // make the indent 4 spaces
-                        *line,
-                        s,
-                        s.len(),
-                        Style::reset().fg(colors.ident),
-                    ).0
-                }
-            },
-            Self::String(s)=>{
-                let indent=(level*4)+offset;
-                if let Some(cursor)=cursor {
-                    if cursor.len()==1 {
-                        if cursor==&[s.len()] {
-                            let style=Style::reset()
-                                .fg(colors.string);
-                            let mut last_column=buf.set_stringn(indent,*line,"\"",1,style).0;
-                            last_column=buf.set_stringn(
-                                last_column,    // make the indent 4 spaces
-                                *line,
-                                s,
-                                s.len(),
-                                style,
-                            ).0;
-                            buf.set_stringn(last_column,*line,"\"",1,style.bg(colors.string).fg(Color::Rgb(0,0,0))).0
-                        } else {
-                            let mut cindices=s.char_indices().skip(cursor[0]);
-                            let index=cindices.next().expect("Invalid cursor position").0;
-                            let after=cindices.next().unwrap_or((s.len(),' ')).0;
-                            let cursor_style=Style::reset()
-                                .fg(Color::Rgb(0,0,0))
-                                .bg(colors.string);
-                            let other_style=Style::reset()
-                                .fg(colors.string);
-                            let mut last_char=buf.set_stringn(indent,*line,"\"",1,other_style).0;
-                            last_char=buf.set_stringn(
-                                last_char,  // make the indent 4 spaces
-                                *line,
-                                &s[..index],
-                                s.len(),
-                                other_style,
-                            ).0;
-                            last_char=buf.set_stringn(
-                                last_char,  // make the indent 4 spaces
-                                *line,
-                                &s[index..after],
-                                s.len(),
-                                cursor_style,
-                            ).0;
-                            last_char=buf.set_stringn(
-                                last_char,  // make the indent 4 spaces
-                                *line,
-                                &s[after..],
-                                s.len(),
-                                other_style,
-                            ).0;
-                            buf.set_stringn(last_char,*line,"\"",1,other_style).0
-                        }
-                    } else {
-                        let style=Style::reset()
-                            .fg(Color::Rgb(0,0,0))
-                            .bg(colors.string);
-                        let mut last_column=buf.set_stringn(indent,*line,"\"",1,style).0;
-                        last_column=buf.set_stringn(
-                            last_column,    // make the indent 4 spaces
-                            *line,
-                            s,
-                            s.len(),
-                            style,
-                        ).0;
-                        buf.set_stringn(last_column,*line,"\"",1,style).0
-                    }
-                } else {
-                    let style=Style::reset().fg(colors.string);
-                    let mut last_column=buf.set_stringn(indent,*line,"\"",1,style).0;
-                    last_column=buf.set_stringn(
-                        last_column,    // make the indent 4 spaces
-                        *line,
-                        s,
-                        s.len(),
-                        style,
-                    ).0;
-                    buf.set_stringn(last_column,*line,"\"",1,style).0
-                }
-            },
-            Self::Number(s)=>{
-                let indent=(level*4)+offset;
-                let s=if s.len()==0 {
-                    "(N)"
-                } else {
-                    s.as_str()
-                };
-                if let Some(cursor)=cursor {
-                    if cursor.len()==1 {
-                        if cursor[0]==s.chars().count() {
-                            let last=buf.set_stringn(
-                                indent, // make the indent 4 spaces
-                                *line,
-                                s,
-                                s.len(),
-                                Style::reset().fg(colors.number)
-                            ).0;
-                            buf.get_mut(last,*line).bg=colors.number;
-                            buf.get_mut(last,*line).fg=Color::Rgb(0,0,0);
-                            return last;
-                        } else {
-                            let mut cindices=s.char_indices().skip(cursor[0]);
-                            let index=cindices.next().expect("Invalid cursor position").0;
-                            let after=cindices.next().unwrap_or((s.len(),' ')).0;
-                            let cursor_style=Style::reset()
-                                .fg(Color::Rgb(0,0,0))
-                                .bg(colors.number);
-                            let other_style=Style::reset()
-                                .fg(colors.number);
-                            let mut last_char=buf.set_stringn(
-                                indent, // make the indent 4 spaces
-                                *line,
-                                &s[..index],
-                                s.len(),
-                                other_style,
-                            ).0;
-                            last_char=buf.set_stringn(
-                                last_char,  // make the indent 4 spaces
-                                *line,
-                                &s[index..after],
-                                s.len(),
-                                cursor_style,
-                            ).0;
-                            buf.set_stringn(
-                                last_char,  // make the indent 4 spaces
-                                *line,
-                                &s[after..],
-                                s.len(),
-                                other_style,
-                            ).0
-                        }
-                    } else {
-                        buf.set_stringn(
-                            indent,   // make the indent 4 spaces
-                            *line,
-                            s,
-                            s.len(),
-                            Style::reset()
-                                .fg(Color::Rgb(0,0,0))
-                                .bg(colors.number),
-                        ).0
-                    }
-                } else {
-                    buf.set_stringn(
-                        indent,   // make the indent 4 spaces
-                        *line,
-                        s,
-                        s.len(),
-                        Style::reset().fg(colors.number),
-                    ).0
-                }
-            },
-            Self::List(items)=>{
-                let style=Style::default()
-                    .fg(colors[level]);
-                let style_rev=Style::reset()
-                    .fg(Color::Rgb(0,0,0))
-                    .bg(colors[level]);
-                let blank_style=Style::reset()
-                    .fg(Color::Rgb(0,0,0))
-                    .bg(colors.ident);
-                let blank_style_rev=Style::reset();
-                match items.as_slice() {
-                    [first,last]=>{
-                        match last {
-                            Self::List(_)=>{    // print the list on a multiple lines
-                                if let Some(cursor)=cursor {
-                                    if cursor.len()>0 {
-                                        let indent=(level*4)+offset;
-                                        buf.set_stringn(
-                                            indent,
-                                            *line,
-                                            "(",
-                                            1,
-                                            style,
-                                        );
-                                        first.render(colors,line,level,offset+1,buf,if cursor[0]==0 {Some(&cursor[1..])}else{None});
-                                        *line+=1;
-                                        let mut last_column=last.render(colors,line,level+1,0,buf,if cursor[0]==1 {Some(&cursor[1..])}else{None});
-                                        if cursor==&[items.len()] {
-                                            last_column=buf.set_stringn(
-                                                last_column,
-                                                *line,
-                                                " ",
-                                                1,
-                                                blank_style_rev,
-                                            ).0;
-                                            last_column=buf.set_stringn(
-                                                last_column,
-                                                *line,
-                                                " ",
-                                                1,
-                                                blank_style,
-                                            ).0;
-                                        }
-                                        buf.set_stringn(
-                                            last_column,
-                                            *line,
-                                            ")",
-                                            1,
-                                            style,
-                                        ).0
-                                    } else {
-                                        let indent=(level*4)+offset;
-                                        buf.set_stringn(
-                                            indent,
-                                            *line,
-                                            "(",
-                                            1,
-                                            style_rev,
-                                        );
-                                        first.render(colors,line,level,offset+1,buf,None);
-                                        *line+=1;
-                                        let last_column=last.render(colors,line,level+1,0,buf,None);
-                                        buf.set_stringn(
-                                            last_column,
-                                            *line,
-                                            ")",
-                                            1,
-                                            style_rev,
-                                        ).0
-                                    }
-                                } else {
-                                    let indent=(level*4)+offset;
-                                    buf.set_stringn(
-                                        indent,
-                                        *line,
-                                        "(",
-                                        1,
-                                        style,
-                                    );
-                                    first.render(colors,line,level,offset+1,buf,None);
-                                    *line+=1;
-                                    let last_column=last.render(colors,line,level+1,0,buf,None);
-                                    buf.set_stringn(
-                                        last_column,
-                                        *line,
-                                        ")",
-                                        1,
-                                        style,
-                                    ).0
-                                }
-                            },
-                            _=>{    // print the list on one line
-                                if let Some(cursor)=cursor {
-                                    if cursor.len()>0 {
-                                        let indent=(level*4)+offset;
-                                        buf.set_stringn(
-                                            indent,
-                                            *line,
-                                            "(",
-                                            1,
-                                            style,
-                                        );
-                                        let mut last_column=first.render(colors,line,level,offset+1,buf,if cursor[0]==0 {Some(&cursor[1..])}else{None})+1;
-                                        last_column=last.render(colors,line,level,last_column-indent,buf,if cursor[0]==1 {Some(&cursor[1..])}else{None});
-                                        if cursor==&[items.len()] {
-                                            last_column=buf.set_stringn(
-                                                last_column,
-                                                *line,
-                                                " ",
-                                                1,
-                                                blank_style_rev,
-                                            ).0;
-                                            last_column=buf.set_stringn(
-                                                last_column,
-                                                *line,
-                                                " ",
-                                                1,
-                                                blank_style,
-                                            ).0;
-                                        }
-                                        buf.set_stringn(
-                                            last_column,
-                                            *line,
-                                            ")",
-                                            1,
-                                            style,
-                                        ).0
-                                    } else {
-                                        let indent=(level*4)+offset;
-                                        buf.set_stringn(
-                                            indent,
-                                            *line,
-                                            "(",
-                                            1,
-                                            style_rev,
-                                        );
-                                        let mut last_column=first.render(colors,line,level,offset+1,buf,None)+1;
-                                        last_column=last.render(colors,line,level,last_column-indent,buf,None);
-                                        buf.set_stringn(
-                                            last_column,
-                                            *line,
-                                            ")",
-                                            1,
-                                            style_rev,
-                                        ).0
-                                    }
-                                } else {
-                                    let indent=(level*4)+offset;
-                                    buf.set_stringn(
-                                        indent,
-                                        *line,
-                                        "(",
-                                        1,
-                                        style,
-                                    );
-                                    let mut last_column=first.render(colors,line,level,offset+1,buf,None)+1;
-                                    last_column=last.render(colors,line,level,last_column-indent,buf,None);
-                                    buf.set_stringn(
-                                        last_column,
-                                        *line,
-                                        ")",
-                                        1,
-                                        style,
-                                    ).0
-                                }
-                            },
-                        }
-                    },
-                    [first,rest@..]=>{
-                        if let Some(cursor)=cursor {
-                            if cursor.len()>0 {
-                                let indent=(level*4)+offset;
-                                buf.set_stringn(
-                                    indent,
-                                    *line,
-                                    "(",
-                                    1,
-                                    style,
-                                );
-                                let mut last_column=first.render(colors,line,level,offset+1,buf,if cursor[0]==0 {Some(&cursor[1..])}else{None});
-                                for (i,item) in rest.iter().enumerate() {
-                                    *line+=1;
-                                    last_column=item.render(colors,line,level+1,0,buf,if cursor[0]==(i+1) {Some(&cursor[1..])}else{None});
-                                }
-                                if cursor==&[items.len()] {
-                                    last_column=buf.set_stringn(
-                                        last_column,
-                                        *line,
-                                        " ",
-                                        1,
-                                        blank_style_rev,
-                                    ).0;
-                                    last_column=buf.set_stringn(
-                                        last_column,
-                                        *line,
-                                        " ",
-                                        1,
-                                        blank_style,
-                                    ).0;
-                                }
-                                buf.set_stringn(
-                                    last_column,
-                                    *line,
-                                    ")",
-                                    1,
-                                    style,
-                                ).0
-                            } else {
-                                let indent=(level*4)+offset;
-                                buf.set_stringn(
-                                    indent,
-                                    *line,
-                                    "(",
-                                    1,
-                                    style_rev,
-                                );
-                                let mut last_column=first.render(colors,line,level,offset+1,buf,None);
-                                for item in rest {
-                                    *line+=1;
-                                    last_column=item.render(colors,line,level+1,0,buf,None);
-                                }
-                                buf.set_stringn(
-                                    last_column,
-                                    *line,
-                                    ")",
-                                    1,
-                                    style_rev,
-                                ).0
-                            }
-                        } else {
-                            let indent=(level*4)+offset;
-                            buf.set_stringn(
-                                indent,
-                                *line,
-                                "(",
-                                1,
-                                style,
-                            );
-                            let mut last_column=first.render(colors,line,level,offset+1,buf,None);
-                            for item in rest {
-                                *line+=1;
-                                last_column=item.render(colors,line,level+1,0,buf,None);
-                            }
-                            buf.set_stringn(
-                                last_column,
-                                *line,
-                                ")",
-                                1,
-                                style,
-                            ).0
-                        }
-                    },
-                    []=>{
-                        let indent=(level*4)+offset;
-                        if let Some(cursor)=cursor {
-                            if cursor==&[items.len()] {
-                                let mut last_column=buf.set_stringn(
-                                    indent,
-                                    *line,
-                                    "(",
-                                    1,
-                                    style,
-                                ).0;
-                                last_column=buf.set_stringn(
-                                    last_column,
-                                    *line,
-                                    " ",
-                                    1,
-                                    blank_style,
-                                ).0;
-                                return buf.set_stringn(
-                                    last_column,
-                                    *line,
-                                    ")",
-                                    1,
-                                    style,
-                                ).0;
-                            }
-                            if cursor.len()==0||cursor.len()==1 {
-                                return buf.set_stringn(
-                                    indent,
-                                    *line,
-                                    "()",
-                                    2,
-                                    style_rev,
-                                ).0;
-                            }
-                        }
-                        buf.set_stringn(
-                            indent,
-                            *line,
-                            "()",
-                            2,
-                            style,
-                        ).0
-                    },
-                }
-            },
-        }
-    }
 }
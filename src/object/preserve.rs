@@ -0,0 +1,246 @@
+//! Writing objects back while keeping the original text of everything that was not edited, so
+//! saving a file after a small change only changes the lines around it.
+use std::ops::Range;
+use super::*;
+
+
+/// The text a buffer was read from. The spans of the objects point into it.
+#[derive(Debug,Clone,Default)]
+pub struct Original {
+    pub text:String,
+    /// Where the text after the last top-level object starts.
+    tail:usize,
+}
+impl Original {
+    pub fn new(text:String,objects:&[Object])->Self {
+        let tail=objects.iter()
+            .filter_map(Object::span)
+            .map(|span|span.end)
+            .max()
+            .unwrap_or(0);
+        Original{text,tail}
+    }
+    /// Makes `text`, which was just written from `objects`, the new original. The spans of the
+    /// objects are moved to where they are in the new text.
+    pub fn saved(text:String,objects:&mut [Object],dialect:&Dialect)->Self {
+        match Object::parse_file(&text,dialect) {
+            Ok(parsed) if parsed.len()==objects.len()=>{
+                let original=Original::new(text,&parsed);
+                for (object,parsed) in objects.iter_mut().zip(parsed) {
+                    object.adopt_spans(parsed);
+                }
+                original
+            },
+            _=>{
+                objects.iter_mut().for_each(Object::forget_spans);
+                let tail=text.len();
+                Original{text,tail}
+            },
+        }
+    }
+}
+
+
+/// Serializes the top-level objects, copying everything that was not edited from the original
+/// text as it was, along with the whitespace and comments before it. Edited objects are laid out
+/// with `format` where they are.
+pub fn serialize_preserving(objects:&[Object],original:&Original,format:&Format)->String {
+    write_preserving(objects,original,format,&mut Vec::new())
+}
+/// Does what `serialize_preserving` does, adding the path and byte range of every node to
+/// `ranges`, parents before their items.
+pub(super) fn write_preserving(objects:&[Object],original:&Original,format:&Format,ranges:&mut Vec<(Vec<usize>,Range<usize>)>)->String {
+    let mut out=String::new();
+    let mut last_new=false;
+    for (i,object) in objects.iter().enumerate() {
+        match object.span() {
+            Some(span)=>{
+                let mut gap=&original.text[span.gap..span.start];
+                if i==0 {
+                    // whatever was before this object was deleted, so don't start with blank lines
+                    gap=gap.trim_start_matches(['\n','\r']);
+                } else if last_new&&!gap.trim_start_matches([' ','\t']).starts_with(['\n','\r']) {
+                    out.push('\n');
+                }
+                out.push_str(gap);
+            },
+            None=>if i>0&&!objects[i-1].is_reader_prefix() {
+                out.push('\n');
+            },
+        }
+        write_object(object,&mut vec![i],original,format,&mut out,ranges);
+        last_new=object.span().is_none();
+    }
+    out.push_str(&original.text[original.tail..]);
+    if !out.is_empty()&&!out.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+fn write_object(object:&Object,path:&mut Vec<usize>,original:&Original,format:&Format,out:&mut String,ranges:&mut Vec<(Vec<usize>,Range<usize>)>) {
+    let text=&original.text;
+    let start=out.len();
+    let index=ranges.len();
+    ranges.push((path.clone(),start..start));
+    match (object,object.span()) {
+        (Object::List(items,..),Some(span)) if object.is_pristine()=>{
+            out.push_str(&text[span.start..span.end]);
+            record_copied(items,path,span.start,start,ranges);
+        },
+        (Object::List(items,delimiter,_),Some(span)) if span.is_list()&&items.iter().any(|item|item.span().is_some())=>{
+            // only the gaps around new items are made up, everything else keeps its text
+            out.push_str(delimiter.open());
+            for (i,item) in items.iter().enumerate() {
+                match item.span() {
+                    Some(item_span)=>{
+                        let gap=&text[item_span.gap..item_span.start];
+                        // whatever was before the first item was deleted
+                        out.push_str(if i==0 {gap.trim_start()} else {gap});
+                    },
+                    None if i==0||items[i-1].is_reader_prefix()=>{},
+                    None=>match new_item_column(items,i,text) {
+                        Some(column)=>{
+                            out.push('\n');
+                            out.push_str(&" ".repeat(column));
+                        },
+                        None=>out.push(' '),
+                    },
+                }
+                path.push(i);
+                write_object(item,path,original,format,out,ranges);
+                path.pop();
+            }
+            out.push_str(&text[span.tail..span.end-1]);
+            out.push_str(delimiter.close());
+        },
+        (Object::List(..),_)=>{
+            let column=out.rsplit('\n').next().unwrap_or("").chars().count();
+            let laid_out=SourceMap::from_layout(&Layout::at(object,column,format));
+            // the layout starts with `column` spaces that are already in the output
+            out.push_str(laid_out.text[column..].trim_end_matches('\n'));
+            for span in laid_out.spans.iter().skip(1) {
+                let mut item_path=path.clone();
+                item_path.extend_from_slice(&span.path[1..]);
+                ranges.push((item_path,span.bytes.start+start-column..span.bytes.end+start-column));
+            }
+        },
+        (_,Some(span)) if !span.edited=>out.push_str(&text[span.start..span.end]),
+        _=>out.push_str(&object.to_string()),
+    }
+    ranges[index].1.end=out.len();
+}
+/// The column a new item at `index` of an edited list goes on a line of its own at: that of the
+/// nearest item after it, or else before it, that starts its own line in the original text. `None`
+/// if the items around it share a line, so it goes after a space.
+fn new_item_column(items:&[Object],index:usize,text:&str)->Option<usize> {
+    let after=items[index+1..].iter().filter_map(Object::span).next();
+    let before=items[..index].iter().rev().filter_map(Object::span).next();
+    let span=after.or(before)?;
+    if !text[span.gap..span.start].contains('\n') {
+        return None;
+    }
+    let line_start=text[..span.start].rfind('\n').map_or(0,|i|i+1);
+    Some(text[line_start..span.start].chars().count())
+}
+/// The comments between the items of a list read from `text` and of the lists in it, to lay it out
+/// again with `Layout::commented`.
+pub fn comments_in(object:&Object,text:&str)->Comments {
+    let mut comments=Comments::new();
+    collect_comments(object,text,&mut comments);
+    comments
+}
+fn collect_comments(object:&Object,text:&str,comments:&mut Comments) {
+    if let (Object::List(items,..),Some(span))=(object,object.span()) {
+        let mut add=|gap:&str,before:usize|{
+            let found:Vec<_>=gap.lines()
+                .enumerate()
+                .filter_map(|(i,line)|{
+                    // commas are whitespace in some dialects
+                    let line=line.trim_start_matches([' ','\t',',']).trim_end();
+                    (!line.is_empty()).then(||Comment{text:line.to_string(),trailing:i==0})
+                })
+                .collect();
+            if !found.is_empty() {
+                comments.insert(before,found);
+            }
+        };
+        for item in items.iter() {
+            if let Some(item_span)=item.span() {
+                add(&text[item_span.gap..item_span.start],item_span.start);
+            }
+        }
+        if span.is_list() {
+            add(&text[span.tail..span.end-1],span.end-1);
+        }
+        items.iter().for_each(|item|collect_comments(item,text,comments));
+    }
+}
+/// Records where the items of a list that was copied as it was ended up. `from` is where the list
+/// starts in the original text and `to` where it starts in the output.
+fn record_copied(items:&[Object],path:&mut Vec<usize>,from:usize,to:usize,ranges:&mut Vec<(Vec<usize>,Range<usize>)>) {
+    for (i,item) in items.iter().enumerate() {
+        if let Some(span)=item.span() {
+            path.push(i);
+            ranges.push((path.clone(),span.start-from+to..span.end-from+to));
+            if let Object::List(items,..)=item {
+                record_copied(items,path,from,to,ranges);
+            }
+            path.pop();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(source:&str)->(Vec<Object>,Original) {
+        let objects=Object::parse_file(source,Dialect::generic()).unwrap();
+        let original=Original::new(source.to_string(),&objects);
+        (objects,original)
+    }
+    fn ident(s:&str)->Object {
+        Object::Ident(s.to_string(),None)
+    }
+
+    #[test]
+    fn untouched_text_is_copied() {
+        let source="; header\n(a   b)\n\n(c ; note\n d)\n";
+        let (objects,original)=read(source);
+        assert_eq!(serialize_preserving(&objects,&original,&Format::default()),source);
+    }
+    #[test]
+    fn adding_an_item_keeps_comments() {
+        let (mut objects,original)=read("(define (f x)\n  ; one\n  (g x)\n  ; two\n  (h x))\n");
+        objects[0].add_object(&[4],Object::List(vec![ident("k"),ident("x")],Delimiter::Paren,None));
+        assert_eq!(
+            serialize_preserving(&objects,&original,&Format::default()),
+            "(define (f x)\n  ; one\n  (g x)\n  ; two\n  (h x)\n  (k x))\n",
+        );
+    }
+    #[test]
+    fn removing_an_item_keeps_the_rest() {
+        let (mut objects,original)=read("(define (f x)\n  ; one\n  (g   x)\n  ; two\n  (h x))\n");
+        objects[0].remove(&[1]);
+        assert_eq!(
+            serialize_preserving(&objects,&original,&Format::default()),
+            "(define\n  ; one\n  (g   x)\n  ; two\n  (h x))\n",
+        );
+        objects[0].remove(&[0]);
+        assert_eq!(
+            serialize_preserving(&objects,&original,&Format::default()),
+            "(; one\n  (g   x)\n  ; two\n  (h x))\n",
+        );
+    }
+    #[test]
+    fn new_items_follow_the_layout_around_them() {
+        let (mut objects,original)=read("(a b c)\n(list (x   y) ; note\n      z)\n");
+        objects[0].add_object(&[1],ident("new"));
+        objects[1].add_object(&[3],ident("w"));
+        assert_eq!(
+            serialize_preserving(&objects,&original,&Format::default()),
+            "(a new b c)\n(list (x   y) ; note\n      z\n      w)\n",
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Where every node is in serialized text, so anything that talks about the text, like a language
+//! server or `grep -n`, can be mapped onto the tree and back.
+use std::{
+    ops::Range,
+    iter::once,
+};
+use super::*;
+
+
+/// Where a node is in the text. The ranges end just after the node.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SourceSpan {
+    pub path:Vec<usize>,
+    pub bytes:Range<usize>,
+    pub start:Position,
+    pub end:Position,
+}
+
+
+/// Serialized text together with where each node ended up in it.
+#[derive(Debug,Clone,Default)]
+pub struct SourceMap {
+    pub text:String,
+    /// Every node in the order they start, so a node is followed by its descendants.
+    pub spans:Vec<SourceSpan>,
+    /// The byte offset each line starts at.
+    lines:Vec<usize>,
+}
+impl SourceMap {
+    /// Maps the text `serialize` writes.
+    pub fn new(objects:&[Object],format:&Format)->Self {
+        Self::from_layout(&Layout::new(objects,format))
+    }
+    /// Maps the text `serialize_preserving` writes.
+    pub fn preserving(objects:&[Object],original:&Original,format:&Format)->Self {
+        let mut ranges=Vec::new();
+        let text=write_preserving(objects,original,format,&mut ranges);
+        let mut map=Self::from_text(text);
+        map.spans=ranges.into_iter()
+            .map(|(path,bytes)|SourceSpan {
+                path,
+                start:map.position(bytes.start),
+                end:map.position(bytes.end),
+                bytes,
+            })
+            .collect();
+        map
+    }
+    /// Maps the text of a layout. The positions of its nodes are kept as they are.
+    pub fn from_layout(layout:&Layout)->Self {
+        let mut map=Self::from_text(layout.text());
+        map.spans=layout.nodes.iter()
+            .map(|node|SourceSpan {
+                path:node.path.clone(),
+                bytes:map.offset(node.start).unwrap_or(map.text.len())..map.offset(node.end).unwrap_or(map.text.len()),
+                start:node.start,
+                end:node.end,
+            })
+            .collect();
+        map
+    }
+    fn from_text(text:String)->Self {
+        let lines=once(0)
+            .chain(text.match_indices('\n').map(|(i,_)|i+1))
+            .collect();
+        SourceMap{text,spans:Vec::new(),lines}
+    }
+    /// The line and column of a byte offset. Columns count chars.
+    pub fn position(&self,offset:usize)->Position {
+        let line=self.lines.partition_point(|start|*start<=offset).saturating_sub(1);
+        let start=self.lines[line];
+        Position {
+            line,
+            column:self.text.get(start..offset).map_or(0,|text|text.chars().count()),
+        }
+    }
+    /// The byte offset of a line and column, if the line has that many chars. The column just
+    /// past the end of the line is where its newline is.
+    pub fn offset(&self,position:Position)->Option<usize> {
+        let start=*self.lines.get(position.line)?;
+        let end=self.lines.get(position.line+1).map_or(self.text.len(),|next|next-1);
+        self.text[start..end].char_indices()
+            .map(|(i,_)|start+i)
+            .chain(once(end))
+            .nth(position.column)
+    }
+    /// Where the node at `path` is.
+    pub fn span(&self,path:&[usize])->Option<&SourceSpan> {
+        self.spans.iter().find(|span|span.path==path)
+    }
+    /// The path of the deepest node containing the byte at `offset`.
+    pub fn path_at(&self,offset:usize)->Option<&[usize]> {
+        self.spans.iter()
+            .filter(|span|span.bytes.contains(&offset))
+            .max_by_key(|span|span.path.len())
+            .map(|span|span.path.as_slice())
+    }
+    pub fn path_at_position(&self,position:Position)->Option<&[usize]> {
+        self.path_at(self.offset(position)?)
+    }
+    /// The path of the deepest node containing the first thing on `line`, which counts from 0
+    /// like the lines of positions do.
+    pub fn path_at_line(&self,line:usize)->Option<&[usize]> {
+        let start=*self.lines.get(line)?;
+        let end=self.lines.get(line+1).copied().unwrap_or(self.text.len());
+        let first=self.text[start..end].find(|c:char|!c.is_whitespace())?;
+        self.path_at(start+first)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source:&str)->Vec<Object> {
+        Object::parse_file(source,Dialect::generic()).unwrap()
+    }
+
+    #[test]
+    fn positions_and_offsets() {
+        let map=SourceMap::new(&parse("(é b)\n(c)"),&Format::default());
+        assert_eq!(map.text,"(é b)\n(c)\n");
+        // columns count chars, offsets count bytes
+        assert_eq!(map.position(4),Position{line:0,column:3});
+        assert_eq!(map.offset(Position{line:0,column:3}),Some(4));
+        assert_eq!(map.position(8),Position{line:1,column:1});
+        assert_eq!(map.offset(Position{line:1,column:1}),Some(8));
+        // the end of a line is where its newline is
+        assert_eq!(map.offset(Position{line:0,column:5}),Some(6));
+        assert_eq!(map.offset(Position{line:0,column:6}),None);
+        assert_eq!(map.offset(Position{line:5,column:0}),None);
+    }
+    #[test]
+    fn paths_to_positions() {
+        let map=SourceMap::new(&parse("(a (b c))\n(d)"),&Format::default());
+        let span=map.span(&[0,1,1]).unwrap();
+        assert_eq!((span.start,span.end),(Position{line:0,column:6},Position{line:0,column:7}));
+        assert_eq!(&map.text[span.bytes.clone()],"c");
+        let span=map.span(&[1]).unwrap();
+        assert_eq!((span.start,span.end),(Position{line:1,column:0},Position{line:1,column:3}));
+        assert!(map.span(&[2]).is_none());
+    }
+    #[test]
+    fn positions_to_paths() {
+        let map=SourceMap::new(&parse("(a (b c))\n(d)"),&Format::default());
+        assert_eq!(map.path_at_position(Position{line:0,column:4}),Some(&[0,1,0][..]));
+        assert_eq!(map.path_at_position(Position{line:0,column:5}),Some(&[0,1][..]));
+        assert_eq!(map.path_at_position(Position{line:1,column:1}),Some(&[1,0][..]));
+        assert_eq!(map.path_at_position(Position{line:0,column:9}),None);
+        assert_eq!(map.path_at_line(1),Some(&[1][..]));
+        assert_eq!(map.path_at_line(2),None);
+    }
+    #[test]
+    fn preserved_text() {
+        let source="; header\n(a   ; note\n  (b c))\n";
+        let objects=parse(source);
+        let original=Original::new(source.to_string(),&objects);
+        let map=SourceMap::preserving(&objects,&original,&Format::default());
+        assert_eq!(map.text,source);
+        let span=map.span(&[0,1,0]).unwrap();
+        assert_eq!((span.start,span.end),(Position{line:2,column:3},Position{line:2,column:4}));
+        assert_eq!(map.path_at_position(Position{line:2,column:5}),Some(&[0,1,1][..]));
+        assert_eq!(map.path_at_line(0),None);
+        assert_eq!(map.path_at_line(1),Some(&[0][..]));
+        assert_eq!(map.path_at_line(2),Some(&[0,1][..]));
+    }
+}
@@ -0,0 +1,158 @@
+//! What each kind of atom may contain, so typing can't produce text that reads back as something
+//! else.
+use super::*;
+
+
+/// The names `Object::kind` gives.
+pub const KINDS:&[&str]=&["symbol","string","number","list"];
+
+
+/// Converts the object at `path` with `Object::convert`. The list it is in is marked edited, so
+/// saving with `serialize_preserving` writes the converted object.
+pub fn convert_at(objects:&mut [Object],path:&[usize],kind:&str)->Result<(),String> {
+    object_at_mut(objects,path)
+        .ok_or_else(||"There is nothing to convert here".to_string())?
+        .convert(kind)?;
+    if let Some((_,parent))=path.split_last() {
+        if let Some(Object::List(_,_,span))=object_at_mut(objects,parent) {
+            mark_edited(span);
+        }
+    }
+    Ok(())
+}
+
+
+/// Checks if the text of a number atom reads as a number: an integer, a decimal with an optional
+/// exponent, a ratio like `1/3`, or an integer in another base like `#xFF` or `0xFF`.
+pub fn is_number(text:&str)->bool {
+    let radix=[("#x",16),("#X",16),("#o",8),("#b",2),("0x",16)].iter()
+        .find_map(|(prefix,radix)|Some((text.strip_prefix(prefix)?,*radix)));
+    if let Some((digits,radix))=radix {
+        let digits=digits.strip_prefix(['+','-']).unwrap_or(digits);
+        return !digits.is_empty()&&digits.chars().all(|c|c.is_digit(radix));
+    }
+    let text=text.strip_prefix(['+','-']).unwrap_or(text);
+    if let Some((numerator,denominator))=text.split_once('/') {
+        return [numerator,denominator].iter().all(|part|!part.is_empty()&&part.chars().all(|c|c.is_ascii_digit()));
+    }
+    // Clojure marks big integers and decimals with a suffix
+    let text=text.strip_suffix(['N','M']).unwrap_or(text);
+    text.starts_with(|c:char|c.is_ascii_digit()||c=='.')&&
+        text.chars().all(|c|c.is_ascii_digit()||".eE+-".contains(c))&&
+        text.parse::<f64>().is_ok()
+}
+
+
+impl Object {
+    /// What the kind of object is called in messages.
+    pub fn kind(&self)->&'static str {
+        match self {
+            Self::List(..)=>"list",
+            Self::Ident(..)=>"symbol",
+            Self::Number(..)=>"number",
+            Self::String(..)=>"string",
+        }
+    }
+    /// Checks if `c` can be typed into the atom at all. Chars that could be part of a valid atom
+    /// are accepted even if the text isn't valid yet, like the `e` in `1e5`.
+    pub fn accepts(&self,c:char)->bool {
+        match self {
+            Self::List(..)=>false,
+            Self::Ident(..)=>!c.is_whitespace()&&!"()[]{}\";".contains(c),
+            Self::Number(..)=>c.is_ascii_hexdigit()||"+-./#xXoNM".contains(c),
+            Self::String(..)=>true,
+        }
+    }
+    /// Why the text of the atom doesn't read back as the same atom, if it doesn't. Strings are
+    /// escaped when they are written, so any string is fine.
+    pub fn problem(&self)->Option<String> {
+        match self {
+            Self::Ident(symbol,_) if symbol.is_empty()=>Some("Empty symbol, which is saved as nothing".to_string()),
+            Self::Ident(symbol,_) if is_number(symbol)=>Some(format!("`{}` would be read as a number",symbol)),
+            Self::Number(number,_) if !is_number(number)=>Some(format!("`{}` is not a number",number)),
+            _=>None,
+        }
+    }
+    /// Turns the object into another kind in place: an atom into another atom with the same
+    /// text, an atom into a list holding it, or a list of one item into that item. `kind` is a
+    /// name from `Object::kind`. Conversions that would give an invalid atom are refused.
+    pub fn convert(&mut self,kind:&str)->Result<(),String> {
+        if !KINDS.contains(&kind) {
+            return Err(format!("Unknown kind `{}`. Use one of {}",kind,KINDS.join(", ")));
+        }
+        if kind==self.kind() {
+            return Err(format!("It is already a {}",kind));
+        }
+        let span=self.span().map(|span|Span{edited:true,..*span});
+        match self {
+            _ if kind=="list"=>{
+                let mut item=Self::List(Vec::new(),Delimiter::Paren,span);
+                swap(self,&mut item);
+                if let Self::List(items,..)=self {
+                    items.push(item);
+                }
+            },
+            Self::List(items,..)=>{
+                if items.len()!=1 {
+                    return Err("Only a list with one item can be turned into it".to_string());
+                }
+                if items[0].kind()!=kind {
+                    items[0].convert(kind)?;
+                }
+                let mut item=items.pop().unwrap();
+                // the atom takes the place of the list in the text, like a list made from an atom
+                if let Self::Ident(_,item_span)|Self::Number(_,item_span)|Self::String(_,item_span)=&mut item {
+                    *item_span=span.map(|span|Span{tail:span.end,..span});
+                }
+                *self=item;
+            },
+            Self::Ident(text,_)|Self::Number(text,_)|Self::String(text,_)=>{
+                let text=text.clone();
+                let converted=match kind {
+                    "symbol"=>Self::Ident(text.clone(),span),
+                    "number"=>Self::Number(text.clone(),span),
+                    _=>Self::String(text.clone(),span),
+                };
+                if let Some(c)=text.chars().find(|c|!converted.accepts(*c)) {
+                    return Err(format!("`{}` can't be part of a {}",c.escape_default(),kind));
+                }
+                if let Some(problem)=converted.problem() {
+                    return Err(problem);
+                }
+                *self=converted;
+            },
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        for number in ["0","42","-7","+7","3.14",".5","-0.5","1e5","1E5","2.5e-3","1/3","-1/3","#xFF","#b101","#o17","0xff","42N","1.5M"] {
+            assert!(is_number(number),"`{}` is a number",number);
+        }
+    }
+    #[test]
+    fn not_numbers() {
+        for text in ["","-","+",".","e5","1e","1/","/3","1/3/4","1..2","#x","#xG","0x","1-2","abc","1a","--1","N"] {
+            assert!(!is_number(text),"`{}` is not a number",text);
+        }
+    }
+    #[test]
+    fn converting_is_saved() {
+        let source="(a (b) c)\n(d)\n";
+        let mut objects=Object::parse_file(source,Dialect::generic()).unwrap();
+        let original=Original::new(source.to_string(),&objects);
+        convert_at(&mut objects,&[0,1],"symbol").unwrap();
+        assert_eq!(serialize_preserving(&objects,&original,&Format::default()),"(a b c)\n(d)\n");
+        convert_at(&mut objects,&[1],"symbol").unwrap();
+        assert_eq!(serialize_preserving(&objects,&original,&Format::default()),"(a b c)\nd\n");
+        convert_at(&mut objects,&[0,2],"list").unwrap();
+        assert_eq!(serialize_preserving(&objects,&original,&Format::default()),"(a b (c))\nd\n");
+    }
+}
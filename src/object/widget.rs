@@ -2,52 +2,319 @@ use tui::{
     style::{
         Style,
         Color,
+        Modifier,
     },
     layout::Rect,
     buffer::Buffer,
-    widgets::Widget,
+    widgets::{
+        StatefulWidget,
+        Widget,
+        Clear,
+    },
 };
 use super::*;
 
 
+/// How many columns the gutter takes, including the space after the markers.
+const GUTTER_WIDTH:u16=2;
+
+
+/// What the object widget remembers between draws: how far it is scrolled and the layout it drew,
+/// so mouse events can be mapped back to cursor paths.
+#[derive(Default)]
+pub struct View {
+    pub scroll:usize,
+    /// Scroll so the cursor is on screen during the next draw.
+    pub follow_cursor:bool,
+    area:Rect,
+    layout:Layout,
+}
+impl View {
+    /// The layout position drawn at a screen position.
+    pub fn position_at(&self,column:u16,row:u16)->Option<Position> {
+        if column<self.area.x||row<self.area.y||column>=self.area.right()||row>=self.area.bottom() {
+            return None;
+        }
+        Some(Position {
+            line:(row-self.area.y) as usize+self.scroll,
+            column:(column-self.area.x) as usize,
+        })
+    }
+    /// Finds the deepest node drawn at a screen position.
+    pub fn node_at(&self,column:u16,row:u16)->Option<&NodeSpan> {
+        self.layout.node_at(self.position_at(column,row)?)
+    }
+    /// The column of a screen position relative to the start of a node on the same line.
+    pub fn column_in(&self,node:&NodeSpan,column:u16)->usize {
+        (column.saturating_sub(self.area.x) as usize).saturating_sub(node.start.column)
+    }
+    pub fn scroll_by(&mut self,amount:isize) {
+        let max=self.layout.lines.saturating_sub(1) as isize;
+        self.scroll=(self.scroll as isize+amount).clamp(0,max.max(0)) as usize;
+    }
+    /// The line the cursor is on: the node it points at, or the end of its parent when it is
+    /// past the last item or inside an atom.
+    fn cursor_line(&self,cursor:&[usize])->Option<usize> {
+        if let Some(node)=self.layout.node(cursor) {
+            return Some(self.layout.nodes[node].start.line);
+        }
+        let parent=&cursor[..cursor.len().saturating_sub(1)];
+        match self.layout.node(parent) {
+            Some(node)=>Some(self.layout.nodes[node].end.line),
+            None=>Some(self.layout.lines),
+        }
+    }
+}
+
+
+/// What the cursor points at in the layout.
+#[derive(PartialEq)]
+enum Target {
+    Nothing,
+    /// A whole node.
+    Node(usize),
+    /// A char in an atom being edited.
+    Char(usize,usize),
+    /// The end of a list, where new items are added.
+    End(usize),
+    /// The end of the file.
+    After,
+}
+
+
 pub struct ObjectWidget<'obj> {
     objects:&'obj [Object],
     colors:&'obj Colors,
     cursor:&'obj [usize],
+    /// The other end of a range of siblings selected with the mouse. The cursor is one end.
+    selection:Option<usize>,
+    format:Option<&'obj Format>,
+    dialect:Option<&'obj Dialect>,
+    /// Dim everything outside the top-level form the cursor is in.
+    dim:bool,
+    diagnostics:&'obj [Diagnostic],
+    /// Leave room on the left for markers on the lines with problems.
+    gutter:bool,
 }
 impl<'obj> ObjectWidget<'obj> {
     pub fn new(objects:&'obj [Object],colors:&'obj Colors,cursor:&'obj [usize])->Self {
-        Self{objects,colors,cursor}
+        Self{objects,colors,cursor,selection:None,format:None,dialect:None,dim:false,diagnostics:&[],gutter:false}
     }
-}
-impl<'obj> Widget for ObjectWidget<'obj> {
-    fn render(self,area:Rect,buf:&mut Buffer) {
-        let mut line=0;
-        buf.reset();
-        if self.cursor.len()>0 {
-            for (i,object) in self.objects.iter().enumerate() {
-                object.render(self.colors,&mut line,0,0,buf,if i==self.cursor[0]{Some(&self.cursor[1..])}else{None});
-                line+=1;
-                if line>area.height {break}
+    pub fn selection(mut self,selection:Option<usize>)->Self {
+        self.selection=selection;
+        self
+    }
+    /// How to lay the objects out. Should be the same format used when saving.
+    pub fn format(mut self,format:&'obj Format)->Self {
+        self.format=Some(format);
+        self
+    }
+    /// Which symbols are highlighted as special forms, definitions and literals.
+    pub fn dialect(mut self,dialect:&'obj Dialect)->Self {
+        self.dialect=Some(dialect);
+        self
+    }
+    pub fn dim(mut self,dim:bool)->Self {
+        self.dim=dim;
+        self
+    }
+    /// Problems to underline.
+    pub fn diagnostics(mut self,diagnostics:&'obj [Diagnostic])->Self {
+        self.diagnostics=diagnostics;
+        self
+    }
+    pub fn gutter(mut self,gutter:bool)->Self {
+        self.gutter=gutter;
+        self
+    }
+    /// Marks each line a problem starts on with the color of the worst problem there.
+    fn draw_gutter(&self,layout:&Layout,area:Rect,buf:&mut Buffer,scroll:usize) {
+        let mut worst:Vec<Option<Severity>>=vec![None;area.height as usize];
+        for diagnostic in self.diagnostics.iter() {
+            let row=layout.node(&diagnostic.path)
+                .and_then(|node|layout.nodes[node].start.line.checked_sub(scroll));
+            if let Some(marker)=row.and_then(|row|worst.get_mut(row)) {
+                *marker=Some(marker.map_or(diagnostic.severity,|severity|severity.min(diagnostic.severity)));
             }
-            if self.cursor[0]==self.objects.len() {
-                let blank_style=Style::reset()
-                    .fg(Color::Rgb(0,0,0))
-                    .bg(self.colors.ident);
-                buf.set_stringn(
-                    0,
-                    line,
-                    " ",
-                    1,
-                    blank_style,
-                );
+        }
+        for (row,severity) in worst.into_iter().enumerate() {
+            if let Some(severity)=severity {
+                buf.set_string(area.x,area.y+row as u16,"●",Style::reset().fg(severity.color(self.colors)));
             }
+        }
+    }
+    /// The path of the list the cursor is in, whose delimiters are highlighted.
+    fn enclosing(&self)->Option<&'obj [usize]> {
+        let parent=&self.cursor[..self.cursor.len().checked_sub(1)?];
+        match object_at(self.objects,parent) {
+            // editing an atom, so its parent is the list
+            Some(Object::Ident(..)|Object::Number(..)|Object::String(..))=>parent.split_last().map(|(_,list)|list),
+            _=>Some(parent),
+        }.filter(|list|!list.is_empty())
+    }
+    fn target(&self,layout:&Layout)->Target {
+        let (last,parent)=match self.cursor.split_last() {
+            Some(split)=>split,
+            None=>return Target::Nothing,
+        };
+        if parent.is_empty()&&*last==self.objects.len() {
+            return Target::After;
+        }
+        if object_at(self.objects,self.cursor).is_some() {
+            return layout.node(self.cursor).map(Target::Node).unwrap_or(Target::Nothing);
+        }
+        match (object_at(self.objects,parent),layout.node(parent)) {
+            (Some(Object::List(..)),Some(node))=>Target::End(node),
+            (Some(_),Some(node))=>Target::Char(node,*last),
+            _=>Target::Nothing,
+        }
+    }
+    fn color(&self,mark:Mark,path:&[usize])->Color {
+        match mark {
+            Mark::Open(depth)|Mark::Close(depth)=>self.colors[depth],
+            Mark::Ident=>match self.dialect.and_then(|dialect|dialect.highlight(self.objects,path)) {
+                Some(Highlight::SpecialForm)=>self.colors.special_form,
+                Some(Highlight::Definition)=>self.colors.definition,
+                Some(Highlight::Constant)=>self.colors.constant,
+                Some(Highlight::Keyword)=>self.colors.keyword,
+                Some(Highlight::Char)=>self.colors.string,
+                None=>self.colors.ident,
+            },
+            Mark::Number=>self.colors.number,
+            Mark::String=>self.colors.string,
+            // only formatting lays out comments
+            Mark::Comment=>self.colors.ident,
+        }
+    }
+    fn draw(&self,area:Rect,buf:&mut Buffer,view:&mut View) {
+        // only this area is cleared, so whatever is drawn around the widget stays
+        Clear.render(area,buf);
+        let layout=match self.format {
+            Some(format)=>Layout::screen(self.objects,format),
+            None=>Layout::screen(self.objects,&Format::default()),
+        };
+        let area=if self.gutter {
+            self.draw_gutter(&layout,area,buf,view.scroll);
+            Rect{x:area.x+GUTTER_WIDTH,width:area.width.saturating_sub(GUTTER_WIDTH),..area}
         } else {
-            for object in self.objects {
-                object.render(self.colors,&mut line,0,0,buf,None);
-                line+=1;
-                if line>area.height {break}
+            area
+        };
+        let mut canvas=Canvas::new(buf,area,view.scroll);
+        let target=self.target(&layout);
+        let enclosing=self.enclosing().and_then(|list|layout.node(list));
+        let top=self.cursor.first().filter(|top|**top<self.objects.len());
+        for piece in layout.pieces.iter() {
+            let path=&layout.nodes[piece.node].path;
+            let color=self.color(piece.mark,path);
+            let mut style=if target==Target::Node(piece.node) {
+                Style::reset().fg(Color::Rgb(0,0,0)).bg(color)
+            } else {
+                Style::reset().fg(color)
+            };
+            if enclosing==Some(piece.node)&&matches!(piece.mark,Mark::Open(_)|Mark::Close(_)) {
+                style=style.bg(self.colors.matching).add_modifier(Modifier::BOLD);
+            }
+            if self.dim&&top.is_some()&&path.first()!=top {
+                style=style.add_modifier(Modifier::DIM);
+            }
+            canvas.set_string(piece.position,&piece.text,style);
+        }
+        for diagnostic in self.diagnostics.iter() {
+            if let Some(node)=layout.node(&diagnostic.path) {
+                canvas.highlight(&layout.nodes[node],Style::default().add_modifier(Modifier::UNDERLINED));
             }
         }
+        let block=Style::reset()
+            .fg(Color::Rgb(0,0,0))
+            .bg(self.colors.ident);
+        match target {
+            // empty atoms take no space, so show a block where they are
+            Target::Node(node) if layout.nodes[node].start==layout.nodes[node].end=>{
+                canvas.set_style(layout.nodes[node].start,block);
+            },
+            Target::Char(node,index)=>if let Some(piece)=layout.pieces.iter().find(|piece|piece.node==node) {
+                let mut position=piece.position;
+                match object_at(self.objects,&layout.nodes[node].path) {
+                    // strings are edited as their value, so step over the quote and escapes
+                    Some(Object::String(s,_))=>{
+                        position.column+=1;
+                        for c in s.chars().take(index) {
+                            if c=='\n' {
+                                position.line+=1;
+                                position.column=0;
+                            } else {
+                                position.column+=escape(c).map_or(1,str::len);
+                            }
+                        }
+                    },
+                    _=>position.column+=index,
+                }
+                canvas.set_style(position,block.bg(self.color(piece.mark,&layout.nodes[node].path)));
+            },
+            Target::End(node)=>if let Some(piece)=layout.pieces.iter().rev().find(|piece|piece.node==node) {
+                canvas.set_style(piece.position,block);
+            },
+            Target::After=>{
+                canvas.set_string(Position{line:layout.lines,column:0}," ",block);
+            },
+            _=>{},
+        }
+        if let (Some(anchor),Some((last,parent)))=(self.selection,self.cursor.split_last()) {
+            let style=Style::default().bg(self.colors.selection);
+            for i in anchor.min(*last)..=anchor.max(*last) {
+                let mut path=parent.to_vec();
+                path.push(i);
+                if let Some(node)=layout.node(&path) {
+                    canvas.highlight(&layout.nodes[node],style);
+                }
+            }
+        }
+        view.area=area;
+        view.layout=layout;
+    }
+}
+impl<'obj> StatefulWidget for ObjectWidget<'obj> {
+    type State=View;
+    fn render(self,area:Rect,buf:&mut Buffer,view:&mut View) {
+        self.draw(area,buf,view);
+        if view.follow_cursor {
+            view.follow_cursor=false;
+            if let Some(line)=view.cursor_line(self.cursor) {
+                let height=area.height as usize;
+                if line<view.scroll||line>=view.scroll+height {
+                    view.scroll=line.saturating_sub(height/2);
+                    self.draw(area,buf,view);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use tui::{
+        backend::TestBackend,
+        widgets::Paragraph,
+        Terminal,
+    };
+    use super::*;
+
+    #[test]
+    fn keeps_what_is_drawn_around_it() {
+        let objects=Object::parse_file("(a b)",Dialect::generic()).unwrap();
+        let colors=Colors::default();
+        let mut view=View::default();
+        let mut term=Terminal::new(TestBackend::new(20,6)).unwrap();
+        term.draw(|f|{
+            let size=f.size();
+            // drawn first, like a pane the editor draws before the objects
+            f.render_widget(Paragraph::new("=> 3"),Rect{y:4,height:2,..size});
+            f.render_stateful_widget(ObjectWidget::new(&objects,&colors,&[0]),Rect{height:4,..size},&mut view);
+        }).unwrap();
+        let buffer=term.backend().buffer();
+        let row=|y|(0..5).map(|x|buffer.get(x,y).symbol.as_str()).collect::<String>();
+        assert_eq!(row(0),"(a b)");
+        assert_eq!(row(4),"=> 3 ");
     }
 }
@@ -0,0 +1,129 @@
+use crate::object::{
+    Format,
+    IndentRules,
+    Dialect,
+    Object,
+    Original,
+    SourceMap,
+    serialize,
+    serialize_preserving,
+};
+
+
+/// Editor settings that can be changed with `:set`.
+#[derive(Debug,Clone)]
+pub struct Options {
+    /// Copy the previous version of a file to `filename~` before saving over it.
+    pub backup:bool,
+    /// Keep the text of everything that was not edited as it was read when saving, instead of
+    /// laying the whole file out again.
+    pub preserve:bool,
+    /// Dim everything outside the top-level form the cursor is in.
+    pub dim:bool,
+    /// Check the buffer for problems as it is edited.
+    pub lint:bool,
+    /// Send yanked objects to the clipboard of the terminal as well.
+    pub clipboard:bool,
+    /// How objects are laid out, both on screen and when saving.
+    pub format:Format,
+    /// How files are read and highlighted.
+    pub dialect:&'static Dialect,
+}
+impl Default for Options {
+    fn default()->Self {
+        Options {
+            backup:false,
+            preserve:false,
+            dim:false,
+            lint:true,
+            clipboard:true,
+            format:Format::default(),
+            dialect:Dialect::generic(),
+        }
+    }
+}
+impl Options {
+    /// Applies one `:set` argument, like `backup`, `nobackup` or `width=100`. Returns a message for
+    /// the command line on error.
+    pub fn set(&mut self,arg:&str)->Result<(),String> {
+        if let Some((name,value))=arg.split_once('=') {
+            match name {
+                "width"=>self.format.width=parse_number(name,value)?,
+                "indent"=>match IndentRules::named(value) {
+                    Some(rules)=>self.format.indent=rules,
+                    None=>return Err(format!("Unknown indent style `{}`. Try one of {}",value,IndentRules::NAMES.join(", "))),
+                },
+                "dialect"=>match Dialect::named(value) {
+                    Some(dialect)=>self.set_dialect(dialect),
+                    None=>return Err(format!("Unknown dialect `{}`. Try one of {}",value,Dialect::names().join(", "))),
+                },
+                _=>return Err(format!("Unknown option `{}`",name)),
+            }
+            return Ok(());
+        }
+        let (name,value)=match arg.strip_prefix("no") {
+            Some(name)=>(name,false),
+            None=>(arg,true),
+        };
+        match name {
+            "backup"=>self.backup=value,
+            "preserve"=>self.preserve=value,
+            "dim"=>self.dim=value,
+            "lint"=>self.lint=value,
+            "clipboard"=>self.clipboard=value,
+            _=>return Err(format!("Unknown option `{}`",arg)),
+        }
+        Ok(())
+    }
+    /// Switches to a dialect along with its indent style. Dialects that have comments turn on
+    /// `preserve`, so saving keeps them.
+    pub fn set_dialect(&mut self,dialect:&'static Dialect) {
+        self.dialect=dialect;
+        self.format.indent=IndentRules::named(dialect.indent).unwrap_or_default();
+        self.preserve|=dialect.preserve;
+    }
+    /// Serializes the buffer the way it is saved. `original` is the text the buffer was read from,
+    /// if there is one.
+    pub fn serialize(&self,objects:&[Object],original:Option<&Original>)->String {
+        match original {
+            Some(original) if self.preserve=>serialize_preserving(objects,original,&self.format),
+            _=>serialize(objects,&self.format),
+        }
+    }
+    /// Serializes the buffer like `serialize`, keeping track of where every node ends up.
+    pub fn source_map(&self,objects:&[Object],original:Option<&Original>)->SourceMap {
+        match original {
+            Some(original) if self.preserve=>SourceMap::preserving(objects,original,&self.format),
+            _=>SourceMap::new(objects,&self.format),
+        }
+    }
+}
+fn parse_number(name:&str,value:&str)->Result<usize,String> {
+    match value.parse() {
+        Ok(0)|Err(_)=>Err(format!("`{}` needs a positive number, not `{}`",name,value)),
+        Ok(n)=>Ok(n),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::object::DIALECTS;
+    use super::*;
+
+    #[test]
+    fn dialects_with_comments_preserve() {
+        for dialect in DIALECTS {
+            assert!(dialect.preserve||!(dialect.line_comments||dialect.block_comments),"`{}` has comments",dialect.name);
+        }
+    }
+    #[test]
+    fn generic_files_keep_comments() {
+        let mut options=Options::default();
+        options.set_dialect(Dialect::for_file("notes.sexp"));
+        let source="; header\n(a b) ; trailing\n";
+        let objects=Object::parse_file(source,options.dialect).unwrap();
+        let original=Original::new(source.to_string(),&objects);
+        assert_eq!(options.serialize(&objects,Some(&original)),source);
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    process::{
+        Command,
+        Child,
+        ChildStdin,
+        Stdio,
+    },
+    io::{
+        Read,
+        Write,
+        Error as IoError,
+        ErrorKind,
+        Result as IoResult,
+    },
+    sync::mpsc::{
+        channel,
+        Sender,
+        Receiver,
+    },
+    thread,
+};
+use crate::nrepl::*;
+
+
+/// How many lines of output are kept.
+const MAX_LINES:usize=500;
+
+
+enum Connection {
+    /// A process that forms are written to over stdin.
+    Process {
+        child:Child,
+        stdin:ChildStdin,
+        receiver:Receiver<String>,
+    },
+    Nrepl(Nrepl),
+}
+
+
+/// Somewhere to evaluate forms: an external REPL process, like `guile` or `sbcl --noinform`, or an
+/// nREPL server. What it prints is collected into lines for the output pane.
+pub struct Repl {
+    /// What the pane is titled with: the command, or the nREPL port.
+    pub name:String,
+    connection:Connection,
+    /// The output so far. The last line is still being written, since prompts don't end with a
+    /// newline.
+    pub lines:Vec<String>,
+}
+impl Repl {
+    /// Starts `command`, split on whitespace into the program and its arguments.
+    pub fn start(command:&str)->IoResult<Self> {
+        let mut words=command.split_whitespace();
+        let program=words.next()
+            .ok_or_else(||IoError::new(ErrorKind::InvalidInput,"no command given"))?;
+        let mut child=Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdin=child.stdin.take().unwrap();
+        let (sender,receiver)=channel();
+        forward(child.stdout.take().unwrap(),sender.clone());
+        forward(child.stderr.take().unwrap(),sender);
+        Ok(Repl {
+            name:command.to_string(),
+            connection:Connection::Process{child,stdin,receiver},
+            lines:vec![String::new()],
+        })
+    }
+    /// Connects to an nREPL server on localhost.
+    pub fn connect(port:u16)->IoResult<Self> {
+        Ok(Repl {
+            name:format!("nREPL on port {}",port),
+            connection:Connection::Nrepl(Nrepl::connect(port)?),
+            lines:vec![String::new()],
+        })
+    }
+    /// Sends code to be evaluated.
+    pub fn send(&mut self,code:&str)->IoResult<()> {
+        match &mut self.connection {
+            Connection::Process{stdin,..}=>{
+                writeln!(stdin,"{}",code)?;
+                stdin.flush()
+            },
+            Connection::Nrepl(nrepl)=>nrepl.eval(code),
+        }
+    }
+    /// Loads a whole file. Only nREPL can do this.
+    pub fn load_file(&mut self,contents:&str,path:&str)->IoResult<()> {
+        self.nrepl()?.load_file(contents,path)
+    }
+    /// Stops the evaluation that is running. Only nREPL can do this.
+    pub fn interrupt(&mut self)->IoResult<()> {
+        self.nrepl()?.interrupt()
+    }
+    /// Asks for the symbols starting with `prefix`. They are printed to the pane. Only nREPL can
+    /// do this.
+    pub fn complete(&mut self,prefix:&str)->IoResult<()> {
+        self.nrepl()?.completions(prefix)
+    }
+    fn nrepl(&mut self)->IoResult<&mut Nrepl> {
+        match &mut self.connection {
+            Connection::Nrepl(nrepl)=>Ok(nrepl),
+            Connection::Process{..}=>Err(IoError::new(ErrorKind::Unsupported,"only nREPL connections can do that")),
+        }
+    }
+    /// Moves whatever the REPL printed since the last call into `lines`. Returns whether there was
+    /// anything.
+    pub fn poll(&mut self)->bool {
+        let mut printed=Vec::new();
+        match &mut self.connection {
+            Connection::Process{receiver,..}=>printed.extend(receiver.try_iter()),
+            Connection::Nrepl(nrepl)=>match nrepl.poll() {
+                Ok(replies)=>for reply in replies {
+                    printed.push(match reply {
+                        Reply::Value(value)=>format!("=> {}\n",value),
+                        Reply::Out(text)|Reply::Err(text)=>text,
+                        Reply::Exception(class)=>format!("Exception: {}\n",class),
+                        Reply::Completions(candidates)=>format!("Completions: {}\n",candidates.join(" ")),
+                        Reply::Status(status)=>format!("[{}]\n",status),
+                        Reply::Closed(reason)=>format!("{}\n",reason),
+                    });
+                },
+                Err(e)=>printed.push(format!("{}\n",e)),
+            },
+        }
+        let any=!printed.is_empty();
+        printed.iter().for_each(|text|self.print(text));
+        any
+    }
+    fn print(&mut self,text:&str) {
+        for (i,line) in text.split('\n').enumerate() {
+            if i>0 {
+                self.lines.push(String::new());
+            }
+            let last=self.lines.last_mut().unwrap();
+            last.extend(line.chars().filter(|c|*c!='\r'));
+        }
+        if self.lines.len()>MAX_LINES {
+            self.lines.drain(..self.lines.len()-MAX_LINES);
+        }
+    }
+    /// Checks if the process is still alive, or the server still connected.
+    pub fn is_running(&mut self)->bool {
+        match &mut self.connection {
+            Connection::Process{child,..}=>matches!(child.try_wait(),Ok(None)),
+            Connection::Nrepl(nrepl)=>nrepl.is_connected(),
+        }
+    }
+}
+impl Drop for Repl {
+    fn drop(&mut self) {
+        if let Connection::Process{child,..}=&mut self.connection {
+            let _=child.kill();
+            let _=child.wait();
+        }
+    }
+}
+
+
+/// Reads `from` on its own thread and sends everything it reads, until it closes.
+fn forward(mut from:impl Read+Send+'static,sender:Sender<String>) {
+    thread::spawn(move||{
+        let mut buf=[0;4096];
+        while let Ok(read)=from.read(&mut buf) {
+            if read==0||sender.send(String::from_utf8_lossy(&buf[..read]).into_owned()).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{
+            TcpListener,
+            TcpStream,
+            Shutdown,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
+    };
+    use super::*;
+
+    fn request(stream:&mut TcpStream,buffer:&mut Vec<u8>)->Bencode {
+        loop {
+            if let Some((message,len))=Bencode::decode(buffer).unwrap() {
+                buffer.drain(..len);
+                return message;
+            }
+            let mut chunk=[0;1024];
+            let len=stream.read(&mut chunk).unwrap();
+            buffer.extend_from_slice(&chunk[..len]);
+        }
+    }
+
+    #[test]
+    fn prints_nrepl_output_to_the_pane() {
+        let listener=TcpListener::bind(("127.0.0.1",0)).unwrap();
+        let port=listener.local_addr().unwrap().port();
+        let server=thread::spawn(move||{
+            let (mut stream,_)=listener.accept().unwrap();
+            let mut buffer=Vec::new();
+            request(&mut stream,&mut buffer);
+            let mut out=Vec::new();
+            Bencode::dict(&[("new-session","s1")]).encode(&mut out);
+            stream.write_all(&out).unwrap();
+            request(&mut stream,&mut buffer);
+            let mut out=Vec::new();
+            Bencode::dict(&[("session","s1"),("out","hi\n")]).encode(&mut out);
+            Bencode::dict(&[("session","s1"),("value","3")]).encode(&mut out);
+            stream.write_all(&out).unwrap();
+            stream.shutdown(Shutdown::Both).unwrap();
+        });
+        let mut repl=Repl::connect(port).unwrap();
+        repl.send("(+ 1 2)").unwrap();
+        let start=Instant::now();
+        while repl.is_running()&&start.elapsed()<Duration::from_secs(5) {
+            repl.poll();
+            thread::sleep(Duration::from_millis(10));
+        }
+        repl.poll();
+        server.join().unwrap();
+        assert_eq!(repl.lines[..2],["hi","=> 3"]);
+    }
+}
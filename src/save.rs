@@ -0,0 +1,85 @@
+use std::{
+    fs::{
+        File,
+        canonicalize,
+        copy,
+        metadata,
+        remove_file,
+        rename,
+        set_permissions,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    io::{
+        Write,
+        Result as IoResult,
+    },
+    time::SystemTime,
+};
+
+
+/// Writes `contents` to `filename` without ever leaving a half written file behind. The data goes
+/// to a temporary file in the same directory first, which is then renamed over the target. The
+/// permissions of the old file are kept, and if `backup` is set the old file is copied to
+/// `filename~` before it is replaced.
+pub fn write_atomic(filename:&str,contents:&str,backup:bool)->IoResult<()> {
+    // write through symlinks instead of replacing them
+    let target=canonicalize(filename).unwrap_or_else(|_|PathBuf::from(filename));
+    let temp=temp_path(&target);
+    if let Err(e)=write_temp(&target,&temp,contents) {
+        let _=remove_file(&temp);
+        return Err(e);
+    }
+    if backup&&target.exists() {
+        if let Err(e)=copy(&target,backup_path(&target)) {
+            let _=remove_file(&temp);
+            return Err(e);
+        }
+    }
+    if let Err(e)=rename(&temp,&target) {
+        let _=remove_file(&temp);
+        return Err(e);
+    }
+    Ok(())
+}
+fn write_temp(target:&Path,temp:&Path,contents:&str)->IoResult<()> {
+    let mut file=File::create(temp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    if let Ok(old)=metadata(target) {
+        set_permissions(temp,old.permissions())?;
+    }
+    Ok(())
+}
+/// The temporary file used while saving `path`: `.filename.tmp` in the same directory.
+fn temp_path(path:&Path)->PathBuf {
+    let name=path.file_name()
+        .map(|n|n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp",name))
+}
+/// The backup of `path`: `filename~` in the same directory.
+fn backup_path(path:&Path)->PathBuf {
+    let name=path.file_name()
+        .map(|n|n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}~",name))
+}
+/// The modification time and size of a file, used to notice when another program changed it.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct FileStamp {
+    modified:SystemTime,
+    len:u64,
+}
+impl FileStamp {
+    /// Reads the stamp of `filename`, or `None` if it does not exist.
+    pub fn read(filename:&str)->Option<Self> {
+        let meta=metadata(filename).ok()?;
+        Some(FileStamp {
+            modified:meta.modified().ok()?,
+            len:meta.len(),
+        })
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    fs::{
+        write as write_file,
+        read_to_string,
+        remove_file,
+        metadata,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+    io::Result as IoResult,
+};
+
+
+/// A copy of the serialized buffer kept next to the file being edited. It is rewritten whenever
+/// the buffer differs from what is on disk, so unsaved work can be recovered after a crash.
+pub struct SwapFile {
+    path:Option<PathBuf>,
+    /// The last contents written to the swap file, so redraws without edits don't touch the disk.
+    last:Option<String>,
+}
+impl SwapFile {
+    pub fn new(filename:Option<&str>)->Self {
+        SwapFile {
+            path:filename.map(swap_path),
+            last:None,
+        }
+    }
+    /// Points the swap file at a new file name, removing the swap file of the old one.
+    pub fn set_filename(&mut self,filename:Option<&str>) {
+        let path=filename.map(swap_path);
+        if path!=self.path {
+            self.remove();
+            self.path=path;
+        }
+    }
+    /// Writes the serialized buffer to the swap file if it changed since the last write.
+    pub fn update(&mut self,out:String)->IoResult<()> {
+        if let Some(path)=&self.path {
+            if self.last.as_ref()!=Some(&out) {
+                write_file(path,&out)?;
+                self.last=Some(out);
+            }
+        }
+        Ok(())
+    }
+    /// Deletes the swap file. Called once the buffer is saved or thrown away.
+    pub fn remove(&mut self) {
+        if let Some(path)=&self.path {
+            if self.last.is_some() {
+                let _=remove_file(path);
+            }
+        }
+        self.last=None;
+    }
+}
+
+
+/// The swap file for `filename`: `.filename.swp` in the same directory.
+pub fn swap_path(filename:&str)->PathBuf {
+    let path=Path::new(filename);
+    let name=path.file_name()
+        .map(|n|n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.swp",name))
+}
+/// Returns the contents of the swap file for `filename` if it exists and is newer than the file
+/// itself (or the file does not exist anymore).
+pub fn recoverable(filename:&str)->Option<String> {
+    let swap=swap_path(filename);
+    let swap_modified=metadata(&swap).and_then(|m|m.modified()).ok()?;
+    if let Ok(file_modified)=metadata(filename).and_then(|m|m.modified()) {
+        if file_modified>=swap_modified {
+            return None;
+        }
+    }
+    read_to_string(swap).ok()
+}